@@ -0,0 +1,45 @@
+#![feature(test)]
+
+extern crate test;
+extern crate rocket_etagged_file_response;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use test::Bencher;
+
+use rocket_etagged_file_response::{EtaggedFileResponseBuilder, EtagMap, IfNoneMatch};
+
+fn temp_file(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("rocket-etagged-file-response-bench-{}-{}", process::id(), name));
+
+    fs::write(&path, vec![b'x'; 64 * 1024]).unwrap();
+
+    path.canonicalize().unwrap()
+}
+
+#[bench]
+fn etag_map_get(b: &mut Bencher) {
+    let path = temp_file("get.bin");
+
+    let etag_map = EtagMap::new();
+
+    EtaggedFileResponseBuilder::new_borrowed(&path).etag_map(&etag_map).build(IfNoneMatch::default()).unwrap();
+
+    b.iter(|| etag_map.get(&path));
+}
+
+#[bench]
+fn not_modified_trusted_cache_hit(b: &mut Bencher) {
+    let path = temp_file("not-modified.bin");
+
+    let etag_map = EtagMap::new();
+
+    let etag = EtaggedFileResponseBuilder::new_borrowed(&path).etag_map(&etag_map).build(IfNoneMatch::default()).unwrap().etag;
+
+    let if_none_match = IfNoneMatch::parse(&format!("\"{}\"", etag));
+
+    b.iter(|| EtaggedFileResponseBuilder::new_borrowed(Path::new(&path)).etag_map(&etag_map).revalidate(false).build(if_none_match.clone()).unwrap());
+}
@@ -0,0 +1,76 @@
+use rocket::Outcome;
+use rocket::request::{self, Request, FromRequest};
+
+/// The request guard used for getting the `Accept-Encoding` header.
+#[derive(Debug, Clone, Default)]
+pub struct AcceptEncoding {
+    /// Content codings in lowercase with their quality values.
+    pub encodings: Vec<(String, f32)>,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for AcceptEncoding {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let mut encodings = Vec::new();
+
+        for value in request.headers().get("Accept-Encoding") {
+            encodings.extend(AcceptEncoding::parse(value).encodings);
+        }
+
+        Outcome::Success(AcceptEncoding {
+            encodings
+        })
+    }
+}
+
+impl AcceptEncoding {
+    /// Parse the value of an `Accept-Encoding` header.
+    pub fn parse(value: &str) -> AcceptEncoding {
+        let encodings = value.split(',').filter_map(|item| {
+            let mut parts = item.split(';');
+
+            let coding = parts.next().unwrap().trim().to_lowercase();
+
+            if coding.is_empty() {
+                return None;
+            }
+
+            let mut quality = 1.0;
+
+            for parameter in parts {
+                let parameter = parameter.trim();
+
+                if parameter.starts_with("q=") || parameter.starts_with("Q=") {
+                    quality = parameter[2..].trim().parse().unwrap_or(0.0);
+                }
+            }
+
+            Some((coding, quality))
+        }).collect();
+
+        AcceptEncoding {
+            encodings
+        }
+    }
+
+    /// The quality value of the given content coding, which is the one of `*` if the coding is not listed, or `0` if neither is listed.
+    pub fn quality(&self, coding: &str) -> f32 {
+        let mut wildcard = None;
+
+        for &(ref c, quality) in self.encodings.iter() {
+            if c == coding {
+                return quality;
+            } else if c == "*" {
+                wildcard = Some(quality);
+            }
+        }
+
+        wildcard.unwrap_or(0.0)
+    }
+
+    /// Whether the given content coding is acceptable.
+    pub fn accepts(&self, coding: &str) -> bool {
+        self.quality(coding) > 0.0
+    }
+}
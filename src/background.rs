@@ -0,0 +1,55 @@
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use etag_map::PendingEtags;
+
+use {EtaggedFileResponse, EtagHasher, EtagCache};
+
+/// A job run on the background hashing thread of an `EtagMap`.
+pub(crate) trait BackgroundJob: Send {
+    fn run(self: Box<Self>);
+}
+
+/// Hash a file and put its etag into the pending etags, unless the file has been changed since it was stat'ed into `cache`.
+pub(crate) struct HashJob<H: EtagHasher> {
+    path: PathBuf,
+    cache: EtagCache,
+    chunk_size: u64,
+    mmap: bool,
+    pending: Arc<PendingEtags>,
+    _hasher: PhantomData<fn() -> H>,
+}
+
+impl<H: EtagHasher> HashJob<H> {
+    pub(crate) fn new(path: PathBuf, cache: EtagCache, chunk_size: u64, mmap: bool, pending: Arc<PendingEtags>) -> HashJob<H> {
+        HashJob {
+            path,
+            cache,
+            chunk_size,
+            mmap,
+            pending,
+            _hasher: PhantomData,
+        }
+    }
+}
+
+impl<H: EtagHasher> BackgroundJob for HashJob<H> {
+    fn run(self: Box<Self>) {
+        let job = *self;
+
+        let mut cache = job.cache;
+
+        if let Ok((etag, file)) = EtaggedFileResponse::hash_file::<H>(&job.path, job.chunk_size, job.mmap) {
+            let is_unchanged = file.metadata().map(|metadata| metadata.len() == cache.size && metadata.modified().ok() == cache.modified).unwrap_or(false);
+
+            if is_unchanged {
+                cache.etag = etag;
+
+                job.pending.push(job.path.clone(), cache);
+            }
+        }
+
+        job.pending.finish_hashing(&job.path);
+    }
+}
@@ -0,0 +1,859 @@
+use std::fs;
+use std::io;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::mem;
+use std::path::Path;
+use std::borrow::Cow;
+use std::time::Duration;
+
+use disposition;
+use content_type;
+
+use hashing_reader::HashingReader;
+
+use {EtaggedFileResponse, EtaggedFileData, EtaggedFileError, EtagMap, EtagCache, EtagSource, EtagHasher, Crc64Hasher, IfNoneMatch, IfModifiedSince, IfMatch, AcceptEncoding, Compression, FileInfo, FILE_RESPONSE_CHUNK_SIZE, IMMUTABLE_CACHE_CONTROL};
+
+/// The builder of `EtaggedFileResponse`.
+///
+/// ```ignore
+/// EtaggedFileResponseBuilder::new("static/app.js")
+///     .etag_map(&etag_map)
+///     .if_modified_since(if_modified_since)
+///     .cache_control("max-age=31536000, immutable")
+///     .build(if_none_match)
+/// ```
+pub struct EtaggedFileResponseBuilder<'a, H: EtagHasher = Crc64Hasher> {
+    path: Cow<'a, Path>,
+    etag_map: Option<&'a EtagMap>,
+    if_modified_since: Option<IfModifiedSince>,
+    if_match: Option<IfMatch>,
+    accept_encoding: Option<AcceptEncoding>,
+    weak_etag: bool,
+    weak: bool,
+    lowercase_etag: bool,
+    etag: Option<String>,
+    chunk_size: u64,
+    content_type: Option<String>,
+    content_disposition: Option<String>,
+    cache_control: Option<String>,
+    vary: Option<String>,
+    nosniff: Option<bool>,
+    cors_origin: Option<String>,
+    digest: bool,
+    expires: bool,
+    immutable: bool,
+    utf8_charset: Option<bool>,
+    compound_extensions: Vec<(String, String)>,
+    extensions: HashMap<String, String>,
+    revalidate: bool,
+    follow_symlinks: bool,
+    canonicalize: bool,
+    max_file_size: Option<(u64, bool)>,
+    single_pass: bool,
+    background_hashing: bool,
+    no_etag: bool,
+    virtual_filename: Option<String>,
+    etag_prefix: Option<String>,
+    age: bool,
+    read_buffer_size: Option<u64>,
+    rate_limit: Option<u64>,
+    cross_origin_resource_policy: Option<String>,
+    cross_origin_embedder_policy: Option<String>,
+    content_language: Option<(String, bool)>,
+    mmap: bool,
+    _hasher: PhantomData<H>,
+}
+
+impl<'a> EtaggedFileResponseBuilder<'a> {
+    /// Create a builder for serving the file at the given path.
+    pub fn new<P: AsRef<Path>>(path: P) -> EtaggedFileResponseBuilder<'a> {
+        Self::with_path(Cow::Owned(path.as_ref().to_path_buf()))
+    }
+
+    /// Create a builder for serving the file at the given path without copying the path.
+    pub fn new_borrowed(path: &'a Path) -> EtaggedFileResponseBuilder<'a> {
+        Self::with_path(Cow::Borrowed(path))
+    }
+
+    fn with_path(path: Cow<'a, Path>) -> EtaggedFileResponseBuilder<'a> {
+        EtaggedFileResponseBuilder {
+            path,
+            etag_map: None,
+            if_modified_since: None,
+            if_match: None,
+            accept_encoding: None,
+            weak_etag: false,
+            weak: false,
+            lowercase_etag: false,
+            etag: None,
+            chunk_size: FILE_RESPONSE_CHUNK_SIZE,
+            content_type: None,
+            content_disposition: None,
+            cache_control: None,
+            vary: None,
+            nosniff: None,
+            cors_origin: None,
+            digest: false,
+            expires: false,
+            immutable: false,
+            utf8_charset: None,
+            compound_extensions: Vec::new(),
+            extensions: HashMap::new(),
+            revalidate: true,
+            follow_symlinks: true,
+            canonicalize: true,
+            max_file_size: None,
+            single_pass: false,
+            background_hashing: false,
+            no_etag: false,
+            virtual_filename: None,
+            etag_prefix: None,
+            age: false,
+            read_buffer_size: None,
+            rate_limit: None,
+            cross_origin_resource_policy: None,
+            cross_origin_embedder_policy: None,
+            content_language: None,
+            mmap: false,
+            _hasher: PhantomData,
+        }
+    }
+}
+
+impl<'a, H: EtagHasher> EtaggedFileResponseBuilder<'a, H> {
+    /// Cache etags in the given `EtagMap`. Without it, the etag is computed every time.
+    pub fn etag_map(mut self, etag_map: &'a EtagMap) -> Self {
+        self.etag_map = Some(etag_map);
+
+        self
+    }
+
+    /// Also respond `304 Not Modified` if the file has not been modified since `If-Modified-Since`, unless the request has `If-None-Match`.
+    pub fn if_modified_since(mut self, if_modified_since: IfModifiedSince) -> Self {
+        self.if_modified_since = Some(if_modified_since);
+
+        self
+    }
+
+    /// Pass the `If-Match` header, so that `single_pass` and `background_hashing` do not leave out the etag it is checked against. Without it, a response whose etag is deferred does not evaluate `If-Match`.
+    pub fn if_match(mut self, if_match: IfMatch) -> Self {
+        self.if_match = Some(if_match);
+
+        self
+    }
+
+    /// Serve a precompressed sibling file (`path.br` or `path.gz`), or compress the file on the fly with the compression features, if the client accepts it.
+    pub fn accept_encoding(mut self, accept_encoding: AcceptEncoding) -> Self {
+        self.accept_encoding = Some(accept_encoding);
+
+        self
+    }
+
+    /// Compute a weak etag from the size and the modification time of the file instead of hashing its content.
+    pub fn weak_etag(mut self, weak_etag: bool) -> Self {
+        self.weak_etag = weak_etag;
+
+        self
+    }
+
+    /// Whether to emit the etag as a weak one (`W/"..."`) even if it is computed from the content, such as for content served through a transforming proxy. It is disabled by default. An etag computed by `weak_etag` is always weak.
+    pub fn weak(mut self, weak: bool) -> Self {
+        self.weak = weak;
+
+        self
+    }
+
+    /// Whether to emit the computed etag in lowercase, such as when migrating from a server which formatted hex etags in lowercase, so that the etags cached by clients keep matching. It is disabled by default, keeping the uppercase hex of `Crc64Hasher`. The etags in the `EtagMap` are not affected.
+    pub fn lowercase_etag(mut self, lowercase_etag: bool) -> Self {
+        self.lowercase_etag = lowercase_etag;
+
+        self
+    }
+
+    /// Use an etag provided by the caller instead of computing one.
+    pub fn etag<S: Into<String>>(mut self, etag: S) -> Self {
+        self.etag = Some(etag.into());
+
+        self
+    }
+
+    /// Set the chunk size used for the streamed body, and for the hashing buffer unless `read_buffer_size` is used. A chunk size of 0, which would end a streamed body at once, is treated as 1.
+    pub fn chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size.max(1);
+
+        self
+    }
+
+    /// Set the size of the buffer for reading a file to be hashed, such as 64 KiB for throughput, independently of the chunk size of the streamed body.
+    pub fn read_buffer_size(mut self, read_buffer_size: u64) -> Self {
+        self.read_buffer_size = Some(read_buffer_size);
+
+        self
+    }
+
+    /// Set the `Content-Type` header, such as `application/wasm`. It replaces the type guessed from the extension of the file entirely.
+    pub fn content_type<S: Into<String>>(mut self, content_type: S) -> Self {
+        self.content_type = Some(content_type.into());
+
+        self
+    }
+
+    /// Set the `Content-Disposition` header.
+    pub fn content_disposition<S: Into<String>>(mut self, content_disposition: S) -> Self {
+        self.content_disposition = Some(content_disposition.into());
+
+        self
+    }
+
+    /// Let the client download the file as an attachment with the given filename, which is independent of the path on disk, such as the original name of a file stored under an opaque key. A non-ASCII filename is sent in the RFC 5987 form with an ASCII fallback.
+    pub fn attachment<S: AsRef<str>>(self, filename: S) -> Self {
+        let content_disposition = disposition::attachment(filename.as_ref());
+
+        self.content_disposition(content_disposition)
+    }
+
+    /// Use the given filename, such as `report.pdf`, instead of the path for guessing the content type of a file stored under a name without an extension. It is also sent as `Content-Disposition: inline; filename="..."` unless `content_disposition` or `attachment` is used, so that browsers save the file under it.
+    pub fn virtual_filename<S: Into<String>>(mut self, virtual_filename: S) -> Self {
+        self.virtual_filename = Some(virtual_filename.into());
+
+        self
+    }
+
+    /// Prepend a namespace, such as a deployment ID, to the computed etag, so that clients revalidate after a deployment even if the content has not changed. The cached etag is not affected, and `If-None-Match` is compared with the prefixed etag.
+    pub fn etag_prefix<S: Into<String>>(mut self, etag_prefix: S) -> Self {
+        self.etag_prefix = Some(etag_prefix.into());
+
+        self
+    }
+
+    /// Whether to emit `Age: 0`, which tells shared caches that the response comes fresh from the origin. It is disabled by default.
+    pub fn age(mut self, age: bool) -> Self {
+        self.age = age;
+
+        self
+    }
+
+    /// Set the `Content-Language` header of a localized file, such as `de` or `en-US`. If `negotiated` is true, which means that the path has been chosen by the `Accept-Language` header, `Accept-Language` is added to the `Vary` header so that shared caches keep the language variants apart.
+    pub fn content_language<S: Into<String>>(mut self, content_language: S, negotiated: bool) -> Self {
+        self.content_language = Some((content_language.into(), negotiated));
+
+        self
+    }
+
+    /// Set the `Cross-Origin-Resource-Policy` header, such as `same-origin` or `cross-origin`, so that the file can be loaded by a cross-origin isolated page. There is none by default.
+    pub fn cross_origin_resource_policy<S: Into<String>>(mut self, policy: S) -> Self {
+        self.cross_origin_resource_policy = Some(policy.into());
+
+        self
+    }
+
+    /// Set the `Cross-Origin-Embedder-Policy` header, such as `require-corp`. There is none by default.
+    pub fn cross_origin_embedder_policy<S: Into<String>>(mut self, policy: S) -> Self {
+        self.cross_origin_embedder_policy = Some(policy.into());
+
+        self
+    }
+
+    /// Limit the throughput of the body to `rate_limit` bytes per second, so that a single large download cannot saturate the uplink. It applies to whole, ranged and compressed bodies alike.
+    pub fn rate_limit(mut self, rate_limit: u64) -> Self {
+        self.rate_limit = Some(rate_limit);
+
+        self
+    }
+
+    /// Set the `Cache-Control` header, either as a string or as a `CacheControl`.
+    pub fn cache_control<S: Into<String>>(mut self, cache_control: S) -> Self {
+        self.cache_control = Some(cache_control.into());
+
+        self
+    }
+
+    /// Set the `Vary` header, such as `Origin`. `Accept-Encoding` is added to it automatically if `accept_encoding` is used.
+    pub fn vary<S: Into<String>>(mut self, vary: S) -> Self {
+        self.vary = Some(vary.into());
+
+        self
+    }
+
+    /// Whether to emit `X-Content-Type-Options: nosniff` to prevent clients from sniffing the content type. It is disabled by default, unless the defaults of the `EtagMap` enable it.
+    pub fn nosniff(mut self, nosniff: bool) -> Self {
+        self.nosniff = Some(nosniff);
+
+        self
+    }
+
+    /// Allow the given origin, or any origin with `*`, to fetch the file cross-origin, with `Access-Control-Allow-Origin` and `Access-Control-Allow-Methods`. No CORS headers are emitted by default.
+    pub fn cors_origin<S: Into<String>>(mut self, origin: S) -> Self {
+        self.cors_origin = Some(origin.into());
+
+        self
+    }
+
+    /// Whether to emit a `Digest` header with a hasher producing digests, such as `Sha256Hasher`, when the etag is the digest of the body sent.
+    pub fn digest(mut self, digest: bool) -> Self {
+        self.digest = digest;
+
+        self
+    }
+
+    /// Whether to emit an `Expires` header for HTTP/1.0 caches, which is the response time plus the `max-age` of the `Cache-Control` header. It is disabled by default, and nothing is emitted without a `max-age`.
+    pub fn expires(mut self, expires: bool) -> Self {
+        self.expires = expires;
+
+        self
+    }
+
+    /// Whether to emit `Cache-Control: public, max-age=31536000, immutable` for fingerprinted assets such as `app.a1b2c3.js`. A header set by `cache_control` takes precedence.
+    pub fn immutable(mut self, immutable: bool) -> Self {
+        self.immutable = immutable;
+
+        self
+    }
+
+    /// Whether to append `; charset=utf-8` to a guessed textual content type, such as `text/*`, `application/javascript` and `application/json`. A content type set by `content_type` is never changed. It is enabled by default, unless the defaults of the `EtagMap` disable it.
+    pub fn utf8_charset(mut self, utf8_charset: bool) -> Self {
+        self.utf8_charset = Some(utf8_charset);
+
+        self
+    }
+
+    /// Map a compound extension, such as `tar.zst`, to a content type. Compound extensions are checked before the last extension of the file, and the ones added here take precedence over the built-in `.tar.gz`, `.tar.bz2` and `.tar.xz`.
+    pub fn compound_extension<E: Into<String>, S: Into<String>>(mut self, extension: E, content_type: S) -> Self {
+        self.compound_extensions.push((extension.into(), content_type.into()));
+
+        self
+    }
+
+    /// Map an extension, such as `glb`, to a content type. It is case-insensitive and takes precedence over the content types known by `mime_guess`.
+    pub fn extension<E: AsRef<str>, S: Into<String>>(mut self, extension: E, content_type: S) -> Self {
+        let extension = extension.as_ref().trim_left_matches('.').to_lowercase();
+
+        self.extensions.insert(extension, content_type.into());
+
+        self
+    }
+
+    /// Map extensions to content types, the same way `extension` does for each of them.
+    pub fn extensions<I: IntoIterator<Item = (String, String)>>(mut self, extensions: I) -> Self {
+        for (extension, content_type) in extensions {
+            self = self.extension(extension, content_type);
+        }
+
+        self
+    }
+
+    /// Whether to check the metadata of the file on a cache hit. It is enabled by default, and without it changes are not noticed until the entry is invalidated or expired.
+    pub fn revalidate(mut self, revalidate: bool) -> Self {
+        self.revalidate = revalidate;
+
+        self
+    }
+
+    /// Whether to serve a file which is a symlink, which may point anywhere on disk. It is enabled by default. If it is disabled, a symlinked file is refused with `EtaggedFileError::NotFound` and a symlinked precompressed sibling is ignored. Symlinked directories in the path are still followed.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+
+        self
+    }
+
+    /// Whether to canonicalize the path, which is also the key of the `EtagMap`. It is enabled by default. Disabling it keeps the keys as given, which is more predictable on bind mounts and overlay filesystems whose real paths can change across restarts, but then differently spelled paths of the same file are cached separately.
+    pub fn canonicalize(mut self, canonicalize: bool) -> Self {
+        self.canonicalize = canonicalize;
+
+        self
+    }
+
+    /// Limit the size of files whose content is hashed, so that a route pointed at an enormous file cannot tie up a worker. A larger file gets a weak etag computed from its metadata if `weak_fallback` is true, or is refused with `EtaggedFileError::TooLarge` otherwise.
+    pub fn max_file_size(mut self, max_file_size: u64, weak_fallback: bool) -> Self {
+        self.max_file_size = Some((max_file_size, weak_fallback));
+
+        self
+    }
+
+    /// Hash the content of files up to `threshold` bytes and give larger ones a weak etag computed from their metadata. It is the same as `max_file_size(threshold, true)`. The cached etag records which scheme produced it, so a file crossing the threshold gets a new one.
+    pub fn weak_above(self, threshold: u64) -> Self {
+        self.max_file_size(threshold, true)
+    }
+
+    /// Whether to hash an uncached file while sending it to an unconditional request, which then gets no `ETag` header. It is disabled by default.
+    pub fn single_pass(mut self, single_pass: bool) -> Self {
+        self.single_pass = single_pass;
+
+        self
+    }
+
+    /// Whether to hash an uncached file on the background thread of the `EtagMap`, serving it without an `ETag` header meanwhile. It is disabled by default.
+    ///
+    /// The response does not wait for the etag. A Rocket 0.3 responder can only wait by blocking its worker, which is what hashing in the background avoids, so the first requests for a cold file are sent without an etag and the following ones get it once it is cached.
+    pub fn background_hashing(mut self, background_hashing: bool) -> Self {
+        self.background_hashing = background_hashing;
+
+        self
+    }
+
+    /// Whether to serve the file without any etag, for files which change too often to be worth caching. It is disabled by default. The file is never hashed, the `EtagMap` is not involved, no `ETag` header is emitted, and conditional requests always get `200 OK`.
+    pub fn no_etag(mut self, no_etag: bool) -> Self {
+        self.no_etag = no_etag;
+
+        self
+    }
+
+    /// Whether to memory-map the file for hashing and the body. It is disabled by default. A mapped file truncated in place can kill the process with `SIGBUS`, so replace files by renaming.
+    #[cfg(feature = "mmap")]
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.mmap = mmap;
+
+        self
+    }
+
+    /// Use another etag hasher.
+    pub fn hasher<T: EtagHasher>(self) -> EtaggedFileResponseBuilder<'a, T> {
+        EtaggedFileResponseBuilder {
+            path: self.path,
+            etag_map: self.etag_map,
+            if_modified_since: self.if_modified_since,
+            if_match: self.if_match,
+            accept_encoding: self.accept_encoding,
+            weak_etag: self.weak_etag,
+            weak: self.weak,
+            lowercase_etag: self.lowercase_etag,
+            etag: self.etag,
+            chunk_size: self.chunk_size,
+            content_type: self.content_type,
+            content_disposition: self.content_disposition,
+            cache_control: self.cache_control,
+            vary: self.vary,
+            nosniff: self.nosniff,
+            cors_origin: self.cors_origin,
+            digest: self.digest,
+            expires: self.expires,
+            immutable: self.immutable,
+            utf8_charset: self.utf8_charset,
+            compound_extensions: self.compound_extensions,
+            extensions: self.extensions,
+            revalidate: self.revalidate,
+            follow_symlinks: self.follow_symlinks,
+            canonicalize: self.canonicalize,
+            max_file_size: self.max_file_size,
+            single_pass: self.single_pass,
+            background_hashing: self.background_hashing,
+            no_etag: self.no_etag,
+            virtual_filename: self.virtual_filename,
+            etag_prefix: self.etag_prefix,
+            age: self.age,
+            read_buffer_size: self.read_buffer_size,
+            rate_limit: self.rate_limit,
+            cross_origin_resource_policy: self.cross_origin_resource_policy,
+            cross_origin_embedder_policy: self.cross_origin_embedder_policy,
+            content_language: self.content_language,
+            mmap: self.mmap,
+            _hasher: PhantomData,
+        }
+    }
+
+    /// Create the `EtaggedFileResponse` instance. The response is `304 Not Modified` if the etag matches `If-None-Match`, or, without `If-None-Match`, if the file has not been modified since `If-Modified-Since`.
+    pub fn build(mut self, if_none_match: IfNoneMatch) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        if let Some(etag_map) = self.etag_map {
+            let defaults = etag_map.defaults();
+
+            if self.cache_control.is_none() && !self.immutable {
+                self.cache_control = defaults.cache_control.clone();
+            }
+
+            self.utf8_charset = self.utf8_charset.or(Some(defaults.utf8_charset));
+            self.nosniff = self.nosniff.or(Some(defaults.nosniff));
+
+            if self.etag_prefix.is_none() {
+                self.etag_prefix = defaults.etag_prefix.clone();
+            }
+        }
+
+        // `canonicalize` resolves symlinks, so they have to be detected before it
+        if !self.follow_symlinks && is_symlink(&self.path) {
+            return Err(EtaggedFileError::NotFound);
+        }
+
+        // a trusted cache hit of a path which is already a key needs no filesystem work at all, which makes a `304 Not Modified` as cheap as possible
+        let key_cache = match self.etag_map {
+            Some(etag_map) if !self.revalidate && !self.no_etag && self.follow_symlinks && self.accept_encoding.is_none() && self.etag.is_none() && self.path.is_absolute() => etag_map.get(&self.path).filter(|cache| self.is_trusted(cache)),
+            _ => None
+        };
+
+        let is_cached_key = key_cache.is_some();
+
+        let original_path = if self.canonicalize && !is_cached_key {
+            match self.path.canonicalize() {
+                Ok(path) => Cow::Owned(path),
+                Err(e) => Err(e)?
+            }
+        } else {
+            // the existence and the type of the file are checked along with its metadata
+            mem::replace(&mut self.path, Cow::Borrowed(Path::new("")))
+        };
+
+        // the path is only owned if a precompressed sibling is served, so the common case does not allocate
+        let (path, content_encoding) = match self.accept_encoding {
+            Some(ref accept_encoding) => EtaggedFileResponse::find_precompressed(&original_path, accept_encoding),
+            None => (Cow::Borrowed(&*original_path), None)
+        };
+
+        let (path, content_encoding) = if !self.follow_symlinks && content_encoding.is_some() && is_symlink(&path) {
+            (Cow::Borrowed(&*original_path), None)
+        } else {
+            (path, content_encoding)
+        };
+
+        let is_caller_etag = self.etag.is_some();
+
+        let trusted_cache = match (key_cache, self.revalidate, self.etag_map, &self.etag) {
+            (Some(cache), _, _, _) => Some(cache),
+            (None, false, Some(etag_map), &None) if !self.no_etag => etag_map.get(&path).filter(|cache| self.is_trusted(cache)),
+            _ => None
+        };
+
+        let mut is_single_pass = false;
+
+        let mut is_background = false;
+
+        let etag_source;
+
+        let (etag, file, file_size, last_modified, cached) = match trusted_cache {
+            Some(cache) => {
+                if let Some(etag_map) = self.etag_map {
+                    etag_map.record_hit();
+                }
+
+                etag_source = EtagSource::CacheHit;
+
+                (cache.etag.clone(), None, cache.size, cache.modified, Some(cache))
+            }
+            None => {
+                // a missing file is not found and an unreadable one is forbidden, so only an existing path of another type is not a file
+                let original_metadata = fs::metadata(&*original_path)?;
+
+                if !original_metadata.is_file() {
+                    return Err(EtaggedFileError::NotAFile);
+                }
+
+                let metadata = if content_encoding.is_some() {
+                    fs::metadata(&path)?
+                } else {
+                    original_metadata
+                };
+
+                let file_size = metadata.len();
+
+                let last_modified = metadata.modified().ok();
+
+                if let Some((max_file_size, weak_fallback)) = self.max_file_size {
+                    if !is_caller_etag && !self.weak_etag && !self.no_etag && file_size > max_file_size {
+                        if weak_fallback {
+                            self.weak_etag = true;
+                        } else {
+                            return Err(EtaggedFileError::TooLarge);
+                        }
+                    }
+                }
+
+                // an `If-Match` with etags is checked against the etag of the response, so the etag cannot be deferred
+                let has_if_match_etags = self.if_match.as_ref().map(|if_match| !if_match.etags.is_empty()).unwrap_or(false);
+
+                let is_unconditional = !has_if_match_etags && !if_none_match.any && if_none_match.etags.is_empty() && match (self.if_modified_since, last_modified) {
+                    (Some(if_modified_since), Some(last_modified)) => !if_modified_since.is_not_modified(last_modified),
+                    _ => true
+                };
+
+                match self.etag.take() {
+                    Some(etag) => {
+                        etag_source = EtagSource::Provided;
+
+                        (etag, None, file_size, last_modified, None)
+                    }
+                    None if self.no_etag => {
+                        etag_source = EtagSource::Disabled;
+
+                        (String::new(), None, file_size, last_modified, None)
+                    }
+                    None if self.single_pass && !self.weak_etag && is_unconditional && self.etag_map.map(|etag_map| EtaggedFileResponse::cached_etag::<H>(etag_map, &path, false, file_size, last_modified).is_none()).unwrap_or(true) => {
+                        is_single_pass = true;
+
+                        etag_source = EtagSource::Deferred;
+
+                        (String::new(), None, file_size, last_modified, None)
+                    }
+                    None if self.background_hashing && !self.weak_etag && !has_if_match_etags && self.etag_map.map(|etag_map| EtaggedFileResponse::cached_etag::<H>(etag_map, &path, false, file_size, last_modified).is_none()).unwrap_or(false) => {
+                        let etag_map = self.etag_map.unwrap();
+
+                        let pending = etag_map.pending();
+
+                        if pending.start_hashing(&path) {
+                            etag_map.record_miss(Duration::from_secs(0));
+
+                            let cache = EtagCache {
+                                etag: String::new(),
+                                algorithm: H::ALGORITHM,
+                                weak: false,
+                                size: file_size,
+                                modified: last_modified,
+                                content_type: self.cacheable_guess(&original_path),
+                            };
+
+                            EtaggedFileResponse::hash_in_background::<H>(path.to_path_buf(), cache, self.read_buffer_size.unwrap_or(self.chunk_size), self.mmap, pending);
+                        }
+
+                        is_background = true;
+
+                        etag_source = EtagSource::Deferred;
+
+                        (String::new(), None, file_size, last_modified, None)
+                    }
+                    None => {
+                        let (cache, file, source) = {
+                            let guess_content_type = || self.cacheable_guess(&original_path);
+
+                            EtaggedFileResponse::etag_of_file::<H>(self.etag_map, &path, self.weak_etag, FileInfo::new(&metadata, self.mmap), self.read_buffer_size.unwrap_or(self.chunk_size), &guess_content_type)?
+                        };
+
+                        etag_source = source;
+
+                        (cache.etag.clone(), file, file_size, last_modified, Some(cache))
+                    }
+                }
+            }
+        };
+
+        let is_hashed = !is_caller_etag && !self.weak_etag && !self.no_etag && !is_single_pass && !is_background;
+
+        // a precompressed sibling warmed by `warm` is cached with its own content type, such as `application/gzip`
+        let guess_path = match self.virtual_filename {
+            Some(ref virtual_filename) => Path::new(virtual_filename),
+            None => &*original_path
+        };
+
+        // the cached guess was made with the built-in extensions only, so it is ignored by a builder with its own
+        let guessed_content_type = match cached {
+            Some(ref cache) if *path == *original_path && self.is_default_guess() => cache.content_type.as_ref(),
+            _ => None
+        };
+
+        // a file without a precompressed sibling may be compressed on the fly, and the etag of the compressed variant must differ
+        // an empty file is never compressed, so that it is always sent as a sized empty body rather than a chunked stream
+        let compression = match self.accept_encoding {
+            Some(ref accept_encoding) if content_encoding.is_none() && file_size > 0 => {
+                let content_type = self.resolve_content_type(guess_path, guessed_content_type);
+
+                Compression::negotiate(accept_encoding, Some(&content_type))
+            }
+            _ => None
+        };
+
+        // the etag of a hasher producing digests is the digest of the content itself
+        let digest = match H::DIGEST_ALGORITHM {
+            Some(algorithm) if self.digest && is_hashed && compression.is_none() => Some(format!("{}={}", algorithm, etag)),
+            _ => None
+        };
+
+        let etag = if self.lowercase_etag && !is_caller_etag {
+            etag.to_lowercase()
+        } else {
+            etag
+        };
+
+        let etag = match compression {
+            // a response without an etag, which has not been computed yet, stays without one
+            Some(compression) if !etag.is_empty() => format!("{}-{}", etag, compression.name()),
+            _ => etag
+        };
+
+        let etag = match self.etag_prefix {
+            Some(ref etag_prefix) if !is_caller_etag && !etag.is_empty() => format!("{}-{}", etag_prefix, etag),
+            _ => etag
+        };
+
+        // `If-Modified-Since` is ignored along with `If-None-Match` as RFC 7232 requires, since a file rewritten within the same second or with its modification time preserved keeps its date but not its etag
+        let has_if_none_match = if_none_match.any || !if_none_match.etags.is_empty();
+
+        let is_etag_match = !self.no_etag && if has_if_none_match {
+            if_none_match.matches(&etag)
+        } else {
+            match (self.if_modified_since, last_modified) {
+                (Some(if_modified_since), Some(last_modified)) => if_modified_since.is_not_modified(last_modified),
+                _ => false
+            }
+        };
+
+        if is_etag_match {
+            if let Some(etag_map) = self.etag_map {
+                etag_map.record_not_modified();
+            }
+        }
+
+        let content_type = if is_etag_match {
+            None
+        } else {
+            Some(self.resolve_content_type(guess_path, guessed_content_type))
+        };
+
+        // the file may be deleted after it is checked, hashed or found in the cache, and before it is opened here, which is a not-found rather than a server error
+        let mut file = file;
+
+        // only a file whose etag is in the `EtagMap` may have its body cached there
+        let memory_body = match self.etag_map {
+            Some(etag_map) if !is_etag_match && !is_caller_etag && !etag.is_empty() => EtaggedFileResponse::memory_body(etag_map, &path, &mut file, file_size, last_modified),
+            _ => Ok(None)
+        };
+
+        let weak = self.weak || self.weak_etag;
+
+        let built = match memory_body {
+            Ok(Some(body)) => Ok(EtaggedFileResponse::new_response(Some(EtaggedFileData::Seekable(body)), etag, weak, content_type, Some(file_size), last_modified)),
+            Ok(None) => {
+                let info = FileInfo {
+                    size: file_size,
+                    modified: last_modified,
+                    mmap: self.mmap,
+                };
+
+                EtaggedFileResponse::build_file_response(&path, content_type, file, info, etag, weak, is_etag_match)
+            }
+            Err(error) => Err(error)
+        };
+
+        let mut response = match built {
+            Ok(response) => response,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                if let Some(etag_map) = self.etag_map {
+                    etag_map.invalidate(&path);
+                }
+
+                return Err(EtaggedFileError::NotFound);
+            }
+            Err(error) => return Err(error.into())
+        };
+
+        if is_single_pass {
+            if let Some(EtaggedFileData::Seekable(data)) = response.data.take() {
+                let cache = EtagCache {
+                    etag: String::new(),
+                    algorithm: H::ALGORITHM,
+                    weak: false,
+                    size: file_size,
+                    modified: last_modified,
+                    content_type: self.cacheable_guess(&original_path),
+                };
+
+                let reader: HashingReader<_, H> = HashingReader::new(data, path.to_path_buf(), cache, self.etag_map.map(|etag_map| etag_map.pending()));
+
+                response.data = Some(EtaggedFileData::Reader(Box::new(reader)));
+            }
+        }
+
+        response.chunk_size = self.chunk_size;
+        response.etag_source = etag_source;
+        response.rate_limit = self.rate_limit;
+        response.cross_origin_resource_policy = self.cross_origin_resource_policy;
+        response.cross_origin_embedder_policy = self.cross_origin_embedder_policy;
+
+        // a body held in the body cache is still served by the origin, which is always the age of 0
+        if self.age {
+            response.age = Some(Duration::from_secs(0));
+        }
+
+        response.content_disposition = match (self.content_disposition, self.virtual_filename) {
+            (Some(content_disposition), _) => Some(content_disposition),
+            (None, Some(virtual_filename)) => Some(disposition::inline(&virtual_filename)),
+            (None, None) => None
+        };
+        response.cache_control = match self.cache_control {
+            Some(cache_control) => Some(cache_control),
+            None if self.immutable => Some(IMMUTABLE_CACHE_CONTROL.to_string()),
+            None => None
+        };
+
+        if self.expires {
+            response = response.expires();
+        }
+
+        response.vary = self.vary;
+        response.nosniff = self.nosniff.unwrap_or(false);
+
+        if self.accept_encoding.is_some() {
+            response = response.add_vary("Accept-Encoding");
+        }
+
+        if let Some((content_language, negotiated)) = self.content_language {
+            if negotiated {
+                response = response.add_vary("Accept-Language");
+            }
+
+            response.content_language = Some(content_language);
+        }
+
+        if let Some(cors_origin) = self.cors_origin {
+            response = response.cors_origin(cors_origin);
+        }
+
+        // a body compressed on the fly has no known length, which a 304 must not claim either
+        if compression.is_some() {
+            response.content_length = None;
+        }
+
+        if !is_etag_match {
+            match compression {
+                Some(compression) => {
+                    response.content_encoding = Some(compression.name().to_string());
+                    response.compression = Some(compression);
+                }
+                None => {
+                    response.content_encoding = content_encoding.map(String::from);
+                    response.digest = digest;
+                }
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Whether a cached etag can be used without checking the metadata of the file, which is only the case if it was produced by the same mode.
+    fn is_trusted(&self, cache: &EtagCache) -> bool {
+        cache.algorithm == H::ALGORITHM && cache.weak == self.weak_etag
+    }
+
+    /// Whether the content type is guessed from the path with the built-in extensions only, which is the only guess shared through the `EtagMap`.
+    fn is_default_guess(&self) -> bool {
+        self.content_type.is_none() && self.virtual_filename.is_none() && self.compound_extensions.is_empty() && self.extensions.is_empty()
+    }
+
+    /// Guess the content type of a path to be cached along with its etag, unless it is not guessed the default way.
+    fn cacheable_guess(&self, path: &Path) -> Option<String> {
+        if self.is_default_guess() {
+            Some(content_type::guess(path, &[], &HashMap::new()))
+        } else {
+            None
+        }
+    }
+
+    /// The explicit content type, or the one guessed from the extension of the file (unless it has been cached) with `charset=utf-8` appended if enabled.
+    fn resolve_content_type(&self, path: &Path, guessed_content_type: Option<&String>) -> String {
+        match self.content_type {
+            Some(ref content_type) => content_type.clone(),
+            None => {
+                let content_type = match guessed_content_type {
+                    Some(content_type) => content_type.clone(),
+                    None => content_type::guess(path, &self.compound_extensions, &self.extensions)
+                };
+
+                if self.utf8_charset.unwrap_or(true) {
+                    content_type::with_utf8_charset(content_type)
+                } else {
+                    content_type
+                }
+            }
+        }
+    }
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).map(|metadata| metadata.file_type().is_symlink()).unwrap_or(false)
+}
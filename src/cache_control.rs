@@ -0,0 +1,119 @@
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Visibility {
+    Public,
+    Private,
+}
+
+/// A typed `Cache-Control` header. Only the directives which have been set are emitted, in a fixed order. It can be passed wherever a `Cache-Control` string is accepted.
+///
+/// ```ignore
+/// EtaggedFileResponseBuilder::new(path)
+///     .cache_control(CacheControl::new().public().max_age(3600).must_revalidate())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    visibility: Option<Visibility>,
+    max_age: Option<u64>,
+    must_revalidate: bool,
+    no_cache: bool,
+    no_store: bool,
+    immutable: bool,
+}
+
+impl CacheControl {
+    /// Create a `Cache-Control` header without any directive.
+    pub fn new() -> CacheControl {
+        CacheControl::default()
+    }
+
+    /// Add `public`, replacing `private`.
+    pub fn public(mut self) -> Self {
+        self.visibility = Some(Visibility::Public);
+
+        self
+    }
+
+    /// Add `private`, replacing `public`.
+    pub fn private(mut self) -> Self {
+        self.visibility = Some(Visibility::Private);
+
+        self
+    }
+
+    /// Add `max-age` in seconds.
+    pub fn max_age(mut self, max_age: u64) -> Self {
+        self.max_age = Some(max_age);
+
+        self
+    }
+
+    /// Add `must-revalidate`.
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+
+        self
+    }
+
+    /// Add `no-cache`, which makes caches revalidate the file on every request, with the etag.
+    pub fn no_cache(mut self) -> Self {
+        self.no_cache = true;
+
+        self
+    }
+
+    /// Add `no-store`.
+    pub fn no_store(mut self) -> Self {
+        self.no_store = true;
+
+        self
+    }
+
+    /// Add `immutable`.
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+
+        self
+    }
+}
+
+impl Display for CacheControl {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut directives: Vec<String> = Vec::with_capacity(6);
+
+        match self.visibility {
+            Some(Visibility::Public) => directives.push("public".to_string()),
+            Some(Visibility::Private) => directives.push("private".to_string()),
+            None => ()
+        }
+
+        if self.no_cache {
+            directives.push("no-cache".to_string());
+        }
+
+        if self.no_store {
+            directives.push("no-store".to_string());
+        }
+
+        if self.must_revalidate {
+            directives.push("must-revalidate".to_string());
+        }
+
+        if let Some(max_age) = self.max_age {
+            directives.push(format!("max-age={}", max_age));
+        }
+
+        if self.immutable {
+            directives.push("immutable".to_string());
+        }
+
+        f.write_str(&directives.join(", "))
+    }
+}
+
+impl From<CacheControl> for String {
+    fn from(cache_control: CacheControl) -> String {
+        cache_control.to_string()
+    }
+}
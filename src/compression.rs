@@ -0,0 +1,104 @@
+use std::io::Read;
+
+#[cfg(feature = "gzip")]
+use flate2;
+
+#[cfg(feature = "brotli")]
+use brotli;
+
+use accept_encoding::AcceptEncoding;
+
+/// Content codings which can be applied to the body on the fly. Each of them is enabled by a feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    #[cfg(feature = "brotli")]
+    Brotli,
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+/// The quality of Brotli on the fly, which trades some ratio for much less CPU time than the maximum of 11.
+#[cfg(feature = "brotli")]
+const BROTLI_QUALITY: u32 = 5;
+
+/// The base-2 logarithm of the Brotli window size.
+#[cfg(feature = "brotli")]
+const BROTLI_LG_WINDOW_SIZE: u32 = 22;
+
+impl Compression {
+    /// Choose the content coding with the highest quality value for a file with the given content type. Binary content types are never compressed. Brotli is preferred over gzip when their quality values are equal.
+    #[allow(unused_variables, unused_mut)]
+    pub(crate) fn negotiate(accept_encoding: &AcceptEncoding, content_type: Option<&str>) -> Option<Compression> {
+        match content_type {
+            Some(content_type) if is_compressible(content_type) => (),
+            _ => return None
+        }
+
+        let mut candidates: Vec<Compression> = Vec::new();
+
+        #[cfg(feature = "brotli")]
+        candidates.push(Compression::Brotli);
+
+        #[cfg(feature = "gzip")]
+        candidates.push(Compression::Gzip);
+
+        let mut negotiated: Option<(Compression, f32)> = None;
+
+        for compression in candidates {
+            let quality = accept_encoding.quality(compression.name());
+
+            if quality > 0.0 && negotiated.map(|(_, negotiated_quality)| quality > negotiated_quality).unwrap_or(true) {
+                negotiated = Some((compression, quality));
+            }
+        }
+
+        negotiated.map(|(compression, _)| compression)
+    }
+
+    /// The name of the content coding.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "brotli")]
+            Compression::Brotli => "br",
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    pub(crate) fn encode<R: Read + 'static>(self, data: R) -> Box<Read> {
+        match self {
+            #[cfg(feature = "brotli")]
+            Compression::Brotli => Box::new(brotli::CompressorReader::new(data, 4096, BROTLI_QUALITY, BROTLI_LG_WINDOW_SIZE)),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => Box::new(flate2::read::GzEncoder::new(data, flate2::Compression::default())),
+        }
+    }
+}
+
+/// Whether the content type is textual and worth compressing.
+pub(crate) fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap().trim().to_lowercase();
+
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || match essence.as_str() {
+        "application/json" | "application/javascript" | "application/xml" | "application/wasm" | "image/svg+xml" => true,
+        _ => false
+    }
+}
+
+#[cfg(all(test, feature = "brotli", feature = "gzip"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_the_higher_quality_value() {
+        assert_eq!(Some(Compression::Gzip), Compression::negotiate(&AcceptEncoding::parse("br;q=0.1, gzip;q=1"), Some("text/html")));
+    }
+
+    #[test]
+    fn prefers_brotli_between_equal_quality_values() {
+        assert_eq!(Some(Compression::Brotli), Compression::negotiate(&AcceptEncoding::parse("gzip, br"), Some("text/html")));
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::Path;
+use std::collections::HashMap;
+
+use mime_guess::get_mime_type_str;
+
+/// Whether the content type is textual and should declare `charset=utf-8`.
+pub(crate) fn is_text(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap().trim().to_lowercase();
+
+    essence.starts_with("text/") || match essence.as_str() {
+        "application/javascript" | "application/json" | "application/xml" => true,
+        _ => false
+    }
+}
+
+/// Append `; charset=utf-8` to a textual content type which has no `charset` parameter. Other content types, and the other parameters of a textual one, are returned unchanged.
+pub(crate) fn with_utf8_charset(content_type: String) -> String {
+    if !is_text(&content_type) {
+        return content_type;
+    }
+
+    // other parameters are kept as they are, and a `charset` one is never replaced
+    let has_charset = content_type.split(';').skip(1).any(|parameter| {
+        let mut parts = parameter.splitn(2, '=');
+
+        parts.next().unwrap().trim().eq_ignore_ascii_case("charset") && parts.next().is_some()
+    });
+
+    if has_charset {
+        content_type
+    } else {
+        format!("{}; charset=utf-8", content_type.trim_right().trim_right_matches(';'))
+    }
+}
+
+/// Compound extensions which are recognized before the last extension, with their content types.
+const COMPOUND_EXTENSIONS: [(&str, &str); 3] = [
+    ("tar.gz", "application/x-gtar"),
+    ("tar.bz2", "application/x-bzip-compressed-tar"),
+    ("tar.xz", "application/x-xz-compressed-tar"),
+];
+
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Guess the content type from the extension of a file. Compound extensions such as `.tar.gz` are checked first, the custom ones before the built-in ones. Then the custom extensions, whose keys are lowercase without the leading dot, are checked before `mime_guess`. An unknown, absent or non-UTF-8 extension results in `application/octet-stream`.
+pub(crate) fn guess(path: &Path, compound_extensions: &[(String, String)], extensions: &HashMap<String, String>) -> String {
+    if let Some(file_name) = path.file_name().and_then(|file_name| file_name.to_str()) {
+        let file_name = file_name.to_lowercase();
+
+        let custom = compound_extensions.iter().map(|&(ref extension, ref content_type)| (extension.as_str(), content_type.as_str()));
+
+        for (extension, content_type) in custom.chain(COMPOUND_EXTENSIONS.iter().cloned()) {
+            let extension = extension.trim_left_matches('.').to_lowercase();
+
+            if file_name.len() > extension.len() + 1 && file_name.ends_with(&extension) && file_name[..(file_name.len() - extension.len())].ends_with('.') {
+                return content_type.to_string();
+            }
+        }
+    }
+
+    // an extension which is not valid UTF-8 cannot be known, so it is treated as absent
+    let content_type = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => {
+            let extension = extension.to_lowercase();
+
+            match extensions.get(&extension) {
+                Some(content_type) => Some(content_type.clone()),
+                None => get_mime_type_str(&extension).map(|t| { String::from(t) })
+            }
+        }
+        None => None
+    };
+
+    content_type.unwrap_or_else(|| String::from(DEFAULT_CONTENT_TYPE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_existing_charset_is_kept() {
+        assert_eq!("text/html; charset=iso-8859-1", with_utf8_charset("text/html; charset=iso-8859-1".to_string()));
+        assert_eq!("application/json; Charset=\"utf-16\"", with_utf8_charset("application/json; Charset=\"utf-16\"".to_string()));
+    }
+
+    #[test]
+    fn other_parameters_are_kept() {
+        assert_eq!("text/plain; format=flowed; charset=utf-8", with_utf8_charset("text/plain; format=flowed".to_string()));
+        assert_eq!("application/manifest+json", with_utf8_charset("application/manifest+json".to_string()));
+    }
+
+    #[test]
+    fn an_override_with_a_charset_round_trips() {
+        let mut extensions = HashMap::new();
+
+        extensions.insert("txt".to_string(), "text/plain; charset=shift_jis".to_string());
+
+        let content_type = guess(Path::new("notes.txt"), &[], &extensions);
+
+        assert_eq!("text/plain; charset=shift_jis", with_utf8_charset(content_type));
+    }
+}
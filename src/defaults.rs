@@ -0,0 +1,23 @@
+/// The options applied to every `EtaggedFileResponse` created with an `EtagMap`, unless they are overridden by the builder. They are usually configured on `EtagFairing`.
+#[derive(Debug, Clone)]
+pub struct EtaggedFileDefaults {
+    /// The default `Cache-Control` header. There is none by default.
+    pub cache_control: Option<String>,
+    /// Whether to append `; charset=utf-8` to guessed textual content types. It is enabled by default.
+    pub utf8_charset: bool,
+    /// Whether to emit `X-Content-Type-Options: nosniff`. It is disabled by default.
+    pub nosniff: bool,
+    /// The namespace prepended to computed etags, such as a deployment ID. There is none by default.
+    pub etag_prefix: Option<String>,
+}
+
+impl Default for EtaggedFileDefaults {
+    fn default() -> Self {
+        EtaggedFileDefaults {
+            cache_control: None,
+            utf8_charset: true,
+            nosniff: false,
+            etag_prefix: None,
+        }
+    }
+}
@@ -0,0 +1,39 @@
+/// Build the value of a `Content-Disposition` header for downloading a file with the given filename. Non-ASCII filenames are encoded in the RFC 5987 `filename*=UTF-8''...` form with an ASCII fallback.
+pub(crate) fn attachment(filename: &str) -> String {
+    with_filename("attachment", filename)
+}
+
+/// Build the value of a `Content-Disposition` header for displaying a file which is saved with the given filename.
+pub(crate) fn inline(filename: &str) -> String {
+    with_filename("inline", filename)
+}
+
+fn with_filename(disposition: &str, filename: &str) -> String {
+    let fallback: String = filename.chars().map(|c| {
+        if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' {
+            c
+        } else {
+            '_'
+        }
+    }).collect();
+
+    if fallback == filename {
+        format!("{}; filename=\"{}\"", disposition, filename)
+    } else {
+        format!("{}; filename=\"{}\"; filename*=UTF-8''{}", disposition, fallback, encode_ext_value(filename))
+    }
+}
+
+/// Percent-encode a string, keeping only the `attr-char` characters defined in RFC 5987.
+fn encode_ext_value(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len() * 3);
+
+    for &b in s.as_bytes() {
+        match b {
+            b'a'...b'z' | b'A'...b'Z' | b'0'...b'9' | b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => encoded.push(b as char),
+            _ => encoded.push_str(&format!("%{:02X}", b))
+        }
+    }
+
+    encoded
+}
@@ -0,0 +1,80 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, ErrorKind};
+
+use rocket::response::{self, Responder};
+use rocket::http::Status;
+use rocket::request::Request;
+
+/// The error of creating an `EtaggedFileResponse` instance from a file. As a responder, `NotFound` and `NotAFile` are responded with `404 Not Found`, `PermissionDenied` with `403 Forbidden`, and `TooLarge` and `Io` with `500 Internal Server Error`.
+#[derive(Debug)]
+pub enum EtaggedFileError {
+    /// The file does not exist, or the path escapes from the directory it is confined to.
+    NotFound,
+    /// The path exists but it is not a regular file, such as a directory.
+    NotAFile,
+    /// The file exists but it cannot be read by the process.
+    PermissionDenied,
+    /// The file is larger than the limit for hashing its content.
+    TooLarge,
+    /// Any other IO error.
+    Io(io::Error),
+}
+
+impl From<io::Error> for EtaggedFileError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            ErrorKind::NotFound => EtaggedFileError::NotFound,
+            ErrorKind::PermissionDenied => EtaggedFileError::PermissionDenied,
+            _ => EtaggedFileError::Io(error)
+        }
+    }
+}
+
+impl Display for EtaggedFileError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            EtaggedFileError::NotFound => f.write_str("the file is not found"),
+            EtaggedFileError::NotAFile => f.write_str("the path is not a file"),
+            EtaggedFileError::PermissionDenied => f.write_str("the permission to read the file is denied"),
+            EtaggedFileError::TooLarge => f.write_str("the file is too large to be hashed"),
+            EtaggedFileError::Io(ref error) => Display::fmt(error, f)
+        }
+    }
+}
+
+impl Error for EtaggedFileError {
+    fn description(&self) -> &str {
+        match *self {
+            EtaggedFileError::NotFound => "the file is not found",
+            EtaggedFileError::NotAFile => "the path is not a file",
+            EtaggedFileError::PermissionDenied => "the permission to read the file is denied",
+            EtaggedFileError::TooLarge => "the file is too large to be hashed",
+            EtaggedFileError::Io(ref error) => error.description()
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            EtaggedFileError::Io(ref error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl EtaggedFileError {
+    /// The status this error is responded with. A file which exists but cannot be read by the process, whether it is found when opening it or when reading its metadata, is `403 Forbidden` rather than `500 Internal Server Error`.
+    pub fn status(&self) -> Status {
+        match *self {
+            EtaggedFileError::NotFound | EtaggedFileError::NotAFile => Status::NotFound,
+            EtaggedFileError::PermissionDenied => Status::Forbidden,
+            EtaggedFileError::TooLarge | EtaggedFileError::Io(_) => Status::InternalServerError
+        }
+    }
+}
+
+impl<'a> Responder<'a> for EtaggedFileError {
+    fn respond_to(self, _: &Request) -> response::Result<'a> {
+        Err(self.status())
+    }
+}
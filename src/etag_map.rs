@@ -0,0 +1,695 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::{self, Sender, SendError};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, Instant, Duration, UNIX_EPOCH};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write, ErrorKind};
+use std::mem;
+use std::thread;
+
+use hasher;
+
+use background::BackgroundJob;
+
+use EtaggedFileDefaults;
+
+/// The first line of a file saved by `EtagMap::save`.
+const SAVED_FILE_HEADER: &str = "rocket-etagged-file-response etag-map 1";
+
+/// A cached etag and the file metadata it was computed from. The size is also used as the `Content-Length` of the response.
+#[derive(Debug, Clone)]
+pub struct EtagCache {
+    pub etag: String,
+    pub algorithm: &'static str,
+    pub weak: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// The content type guessed from the extension of the file, so that a cache hit need not guess it again.
+    pub content_type: Option<String>,
+}
+
+/// A snapshot of the counters of an `EtagMap`, for monitoring how well it works.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EtagStats {
+    /// How many times a cached etag was reused.
+    pub hits: usize,
+    /// How many times an etag was computed because it was not cached or it was stale.
+    pub misses: usize,
+    /// How many times a response was `304 Not Modified`.
+    pub not_modified: usize,
+    /// The total time spent on hashing the content of files.
+    pub hashing_time: Duration,
+    /// The total size of the file bodies cached in memory.
+    pub body_bytes: usize,
+}
+
+/// Where the etag of an `EtaggedFileResponse` came from, for logging how well the `EtagMap` works.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EtagSource {
+    /// The etag was cached, in an `EtagMap` or, for embedded data, per worker thread.
+    CacheHit,
+    /// The etag was computed for this response.
+    Computed,
+    /// The etag was given by the caller.
+    Provided,
+    /// The etag is being computed while the body is sent or on a background thread, so the response has none.
+    Deferred,
+    /// The response has no etag because it was disabled by `no_etag`.
+    Disabled,
+}
+
+/// The etags computed while sending bodies or by background threads, which are moved into the map the next time it is looked up. The map cannot be borrowed by a body or a thread, which outlive the request.
+#[derive(Debug, Default)]
+pub(crate) struct PendingEtags {
+    has_pending: AtomicBool,
+    etags: Mutex<Vec<(PathBuf, EtagCache)>>,
+    hashing: Mutex<HashSet<PathBuf>>,
+    worker: Mutex<Option<Sender<Box<BackgroundJob>>>>,
+}
+
+impl PendingEtags {
+    pub(crate) fn push(&self, path: PathBuf, cache: EtagCache) {
+        self.etags.lock().unwrap().push((path, cache));
+
+        self.has_pending.store(true, Ordering::Release);
+    }
+
+    /// Mark a path as being hashed by a background thread. Returns `false` if a thread is already hashing it.
+    pub(crate) fn start_hashing(&self, path: &Path) -> bool {
+        self.hashing.lock().unwrap().insert(path.to_path_buf())
+    }
+
+    /// Unmark a path after its background thread has finished, whether the etag was computed or not.
+    pub(crate) fn finish_hashing(&self, path: &Path) {
+        self.hashing.lock().unwrap().remove(path);
+    }
+
+    /// Queue a job for the background thread, which is started on first use and shared by all the paths. Returns `false` if the thread cannot be started.
+    pub(crate) fn run_in_background(&self, job: Box<BackgroundJob>) -> bool {
+        let mut worker = self.worker.lock().unwrap();
+
+        let job = match *worker {
+            Some(ref sender) => {
+                match sender.send(job) {
+                    Ok(()) => return true,
+                    // the thread has died, so another one is started
+                    Err(SendError(job)) => job
+                }
+            }
+            None => job
+        };
+
+        let (sender, receiver) = mpsc::channel::<Box<BackgroundJob>>();
+
+        let spawned = thread::Builder::new().name("etag-hashing".to_string()).spawn(move || {
+            for job in receiver {
+                job.run();
+            }
+        });
+
+        match spawned {
+            Ok(_) => {
+                // the receiver is owned by the new thread, so it cannot have been dropped yet
+                let _ = sender.send(job);
+
+                *worker = Some(sender);
+
+                true
+            }
+            Err(_) => {
+                *worker = None;
+
+                false
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    cache: Arc<EtagCache>,
+    inserted: Instant,
+    /// Whether the entry has been used since the clock hand last passed it.
+    referenced: AtomicBool,
+    /// The position of the entry in the ring, which tells a current slot from a stale one.
+    slot: usize,
+    body: Option<Arc<Vec<u8>>>,
+}
+
+/// The entries of an `EtagMap` and, for a map with a capacity, the ring of the clock algorithm which picks the entry to be evicted.
+#[derive(Debug, Default)]
+struct Entries {
+    map: HashMap<PathBuf, Entry>,
+    /// The paths in the order the clock hand passes them, with the slots of their entries. A slot whose entry has been removed or replaced is skipped.
+    ring: VecDeque<(PathBuf, usize)>,
+}
+
+/// This map should be managed by a rocket instance. It is keyed by canonicalized paths, which need not be valid UTF-8. Cache hits only take a read lock.
+///
+/// By default the map is unbounded and its entries never expire. A map created with a capacity evicts the least recently used entry when it is full, and a map created with a TTL treats entries older than the TTL as missing. A map can also cache the bodies of small files, see `body_cache`, and save itself to a file, see `persist`.
+#[derive(Debug, Default)]
+pub struct EtagMap {
+    entries: RwLock<Entries>,
+    computing: Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>,
+    capacity: Option<usize>,
+    ttl: Option<Duration>,
+    clock: AtomicUsize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    not_modified: AtomicUsize,
+    hashing_time: Mutex<Duration>,
+    pending: Arc<PendingEtags>,
+    defaults: EtaggedFileDefaults,
+    body_cache: Option<(u64, usize)>,
+    body_bytes: AtomicUsize,
+    persist: Option<(PathBuf, Duration)>,
+    persisted: Mutex<Option<Instant>>,
+}
+
+impl EtagMap {
+    /// Create a new unbounded EtagMap instance.
+    pub fn new() -> EtagMap {
+        EtagMap::default()
+    }
+
+    /// Create a new EtagMap instance holding at most `capacity` entries.
+    pub fn with_capacity(capacity: usize) -> EtagMap {
+        let mut etag_map = EtagMap::default();
+
+        etag_map.capacity = Some(capacity);
+
+        etag_map
+    }
+
+    /// Create a new unbounded EtagMap instance whose entries expire after `ttl`.
+    pub fn with_ttl(ttl: Duration) -> EtagMap {
+        let mut etag_map = EtagMap::default();
+
+        etag_map.ttl = Some(ttl);
+
+        etag_map
+    }
+
+    pub(crate) fn with_options(capacity: Option<usize>, ttl: Option<Duration>, defaults: EtaggedFileDefaults) -> EtagMap {
+        let mut etag_map = EtagMap::default();
+
+        etag_map.capacity = capacity;
+        etag_map.ttl = ttl;
+        etag_map.defaults = defaults;
+
+        etag_map
+    }
+
+    /// Cache the bodies of files up to `max_file_size` bytes in memory along with their etags, so that they are served without opening the files, until the cached bodies take `budget` bytes in total. A body is dropped along with its etag when the file is changed, invalidated or evicted.
+    pub fn body_cache(mut self, max_file_size: u64, budget: usize) -> Self {
+        self.body_cache = Some((max_file_size, budget));
+
+        self
+    }
+
+    /// Save the map to `path` by `save` when an etag is cached and `interval` has passed since the last save, and when the map is dropped. Rocket 0.3 does not drop its managed state when the process is stopped, so the interval is what keeps the file current on a server. The file is not loaded, see `load_into`.
+    pub fn persist<P: AsRef<Path>>(mut self, path: P, interval: Duration) -> Self {
+        self.persist = Some((path.as_ref().to_path_buf(), interval));
+
+        self
+    }
+
+    /// Save the map if it is persisted and the interval has passed. A failed save is retried after the next interval instead of failing the request which triggered it, and a request which finds another one saving skips it.
+    fn persist_if_due(&self) {
+        if let Some((ref path, interval)) = self.persist {
+            if let Ok(mut persisted) = self.persisted.try_lock() {
+                if persisted.map(|saved| saved.elapsed() >= interval).unwrap_or(true) {
+                    let _ = self.save(path);
+
+                    *persisted = Some(Instant::now());
+                }
+            }
+        }
+    }
+
+    /// The maximum size of a file whose body may be cached, if the body cache is enabled.
+    pub(crate) fn body_cache_max_file_size(&self) -> Option<u64> {
+        self.body_cache.map(|(max_file_size, _)| max_file_size)
+    }
+
+    /// Get the cached body of a path if it was read from the file with the given metadata.
+    pub(crate) fn get_body(&self, path: &Path, size: u64, modified: Option<SystemTime>) -> Option<Arc<Vec<u8>>> {
+        self.entries.read().unwrap().map.get(path).and_then(|entry| {
+            match entry.body {
+                Some(ref body) if entry.cache.size == size && entry.cache.modified == modified => Some(body.clone()),
+                _ => None
+            }
+        })
+    }
+
+    /// Cache the body of a path whose etag has been cached for the file with the given metadata, if the budget allows it.
+    pub(crate) fn insert_body(&self, path: &Path, size: u64, modified: Option<SystemTime>, body: Arc<Vec<u8>>) {
+        let budget = match self.body_cache {
+            Some((_, budget)) => budget,
+            None => return
+        };
+
+        let mut entries = self.entries.write().unwrap();
+
+        if let Some(entry) = entries.map.get_mut(path) {
+            if entry.body.is_none() && entry.cache.size == size && entry.cache.modified == modified && self.body_bytes.load(Ordering::Relaxed) + body.len() <= budget {
+                self.body_bytes.fetch_add(body.len(), Ordering::Relaxed);
+
+                entry.body = Some(body);
+            }
+        }
+    }
+
+    /// Give the budget of the body of a removed entry back.
+    fn release_body(&self, entry: Option<Entry>) -> bool {
+        match entry {
+            Some(entry) => {
+                if let Some(body) = entry.body {
+                    self.body_bytes.fetch_sub(body.len(), Ordering::Relaxed);
+                }
+
+                true
+            }
+            None => false
+        }
+    }
+
+    /// The options applied to the responses created with this map unless they are overridden.
+    pub fn defaults(&self) -> &EtaggedFileDefaults {
+        &self.defaults
+    }
+
+    /// Get the cached etag of a path. An expired entry is treated as missing. The entry is shared rather than copied.
+    pub fn get(&self, path: &Path) -> Option<Arc<EtagCache>> {
+        self.flush_pending();
+
+        self.entries.read().unwrap().map.get(path).and_then(|entry| {
+            if let Some(ttl) = self.ttl {
+                if entry.inserted.elapsed() >= ttl {
+                    return None;
+                }
+            }
+
+            entry.referenced.store(true, Ordering::Relaxed);
+
+            Some(entry.cache.clone())
+        })
+    }
+
+    /// Cache the etag of a path.
+    pub fn insert(&self, path: PathBuf, cache: EtagCache) {
+        self.insert_shared(path, Arc::new(cache));
+    }
+
+    /// Cache the etag of a path which is also held by the caller.
+    pub(crate) fn insert_shared(&self, path: PathBuf, cache: Arc<EtagCache>) {
+        self.insert_entry(path, cache);
+
+        self.persist_if_due();
+    }
+
+    /// Cache the etag of a path without saving a persisted map, for the etags which are loaded or were computed elsewhere.
+    fn insert_entry(&self, path: PathBuf, cache: Arc<EtagCache>) {
+        let mut entries = self.entries.write().unwrap();
+
+        let entries = &mut *entries;
+
+        let slot = self.tick();
+
+        if let Some(capacity) = self.capacity {
+            if capacity == 0 {
+                return;
+            }
+
+            if !entries.map.contains_key(&path) {
+                while entries.map.len() >= capacity && !entries.ring.is_empty() {
+                    self.evict(entries);
+                }
+            }
+
+            entries.ring.push_back((path.clone(), slot));
+        }
+
+        let replaced = entries.map.insert(path, Entry {
+            cache,
+            inserted: Instant::now(),
+            referenced: AtomicBool::new(false),
+            slot,
+            body: None,
+        });
+
+        self.release_body(replaced);
+
+        // stale slots are normally dropped when the clock hand reaches them, but removed and replaced entries can pile them up
+        if entries.ring.len() > entries.map.len() * 2 {
+            let map = &entries.map;
+
+            entries.ring.retain(|&(ref path, slot)| map.get(path).map(|entry| entry.slot == slot).unwrap_or(false));
+        }
+    }
+
+    /// Evict the least recently used entry approximately by the clock algorithm, which gives an entry used since the hand last passed it a second chance. Each slot is passed at most twice, so it takes amortized constant time.
+    fn evict(&self, entries: &mut Entries) {
+        while let Some((path, slot)) = entries.ring.pop_front() {
+            let is_referenced = match entries.map.get(&path) {
+                Some(entry) if entry.slot == slot => entry.referenced.swap(false, Ordering::Relaxed),
+                _ => continue
+            };
+
+            if is_referenced {
+                entries.ring.push_back((path, slot));
+            } else {
+                self.release_body(entries.map.remove(&path));
+
+                return;
+            }
+        }
+    }
+
+    #[inline]
+    fn tick(&self) -> usize {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Remove the cached etag of a path. The path is canonicalized the same way `EtaggedFileResponse::from` does, so relative paths and symlinks match the keys of the map. If the file no longer exists, only its parent directory is canonicalized. The path as given is removed too. Returns whether an entry was removed.
+    pub fn invalidate<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+
+        let key = match path.canonicalize() {
+            Ok(path) => path,
+            Err(_) => {
+                match (path.parent(), path.file_name()) {
+                    (Some(parent), Some(file_name)) => parent.canonicalize().map(|parent| parent.join(file_name)).unwrap_or_else(|_| path.to_path_buf()),
+                    _ => path.to_path_buf()
+                }
+            }
+        };
+
+        let mut entries = self.entries.write().unwrap();
+
+        // a path which is not canonicalized by the builder is cached as given
+        let removed = self.release_body(entries.map.remove(path));
+
+        self.release_body(entries.map.remove(&key)) || removed
+    }
+
+    /// Remove all cached etags and bodies.
+    pub fn clear(&self) {
+        let mut entries = self.entries.write().unwrap();
+
+        entries.map.clear();
+        entries.ring.clear();
+
+        self.body_bytes.store(0, Ordering::Relaxed);
+    }
+
+    /// Save all cached etags to a file, so that they can be reused after a restart by `EtagMap::load`. The file is written to a temporary sibling first and then renamed. Entries whose paths are not valid UTF-8 are skipped.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+
+        let mut temporary_path = path.as_os_str().to_os_string();
+
+        temporary_path.push(".tmp");
+
+        let temporary_path = PathBuf::from(temporary_path);
+
+        self.flush_pending();
+
+        {
+            let mut writer = BufWriter::new(File::create(&temporary_path)?);
+
+            writeln!(writer, "{}", SAVED_FILE_HEADER)?;
+
+            for (path, entry) in self.entries.read().unwrap().map.iter() {
+                let cache = &entry.cache;
+
+                let path = match path.to_str() {
+                    Some(path) => path,
+                    None => continue
+                };
+
+                let modified = match cache.modified.map(|modified| modified.duration_since(UNIX_EPOCH)) {
+                    Some(Ok(modified)) => format!("{}.{}", modified.as_secs(), modified.subsec_nanos()),
+                    Some(Err(_)) => continue,
+                    None => "-".to_string()
+                };
+
+                let content_type = match cache.content_type {
+                    Some(ref content_type) => content_type.as_str(),
+                    None => "-"
+                };
+
+                let fields = [path, cache.etag.as_str(), content_type];
+
+                // a field containing a separator cannot be read back
+                if fields.iter().any(|field| field.contains('\t') || field.contains('\n') || field.contains('\r')) {
+                    continue;
+                }
+
+                writeln!(writer, "{}\t{}\t{}\t{}\t{}\t{}\t{}", cache.algorithm, if cache.weak { 1 } else { 0 }, cache.size, modified, content_type, cache.etag, path)?;
+            }
+
+            writer.flush()?;
+        }
+
+        fs::rename(&temporary_path, path)
+    }
+
+    /// Load the etags saved by `EtagMap::save` into a new unbounded EtagMap instance. Like any cached etag, a loaded one is ignored if the size or the modification time of its file has been changed since it was saved. Entries computed by hashers which are not built into this crate are skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<EtagMap> {
+        let etag_map = EtagMap::new();
+
+        etag_map.load_into(path)?;
+
+        Ok(etag_map)
+    }
+
+    /// Load the etags saved by `EtagMap::save` into this map, keeping its capacity, TTL, body cache and defaults. Returns the number of loaded entries.
+    pub fn load_into<P: AsRef<Path>>(&self, path: P) -> io::Result<usize> {
+        let reader = BufReader::new(File::open(path)?);
+
+        let mut lines = reader.lines();
+
+        match lines.next() {
+            Some(line) => {
+                if line? != SAVED_FILE_HEADER {
+                    return Err(io::Error::new(ErrorKind::InvalidData, "not a saved etag map"));
+                }
+            }
+            None => return Err(io::Error::new(ErrorKind::InvalidData, "not a saved etag map"))
+        }
+
+        let mut count = 0;
+
+        for line in lines {
+            let line = line?;
+
+            if let Some((path, cache)) = Self::parse_saved_line(&line) {
+                self.insert_entry(path, Arc::new(cache));
+
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn parse_saved_line(line: &str) -> Option<(PathBuf, EtagCache)> {
+        let fields: Vec<&str> = line.splitn(7, '\t').collect();
+
+        if fields.len() != 7 {
+            return None;
+        }
+
+        let algorithm = hasher::algorithm(fields[0])?;
+
+        let weak = match fields[1] {
+            "0" => false,
+            "1" => true,
+            _ => return None
+        };
+
+        let size = fields[2].parse().ok()?;
+
+        let modified = if fields[3] == "-" {
+            None
+        } else {
+            let mut parts = fields[3].splitn(2, '.');
+
+            let secs: u64 = parts.next()?.parse().ok()?;
+            let nanos: u32 = parts.next()?.parse().ok()?;
+
+            Some(UNIX_EPOCH + Duration::new(secs, nanos))
+        };
+
+        let content_type = if fields[4] == "-" {
+            None
+        } else {
+            Some(fields[4].to_string())
+        };
+
+        Some((PathBuf::from(fields[6]), EtagCache {
+            etag: fields[5].to_string(),
+            algorithm,
+            weak,
+            size,
+            modified,
+            content_type,
+        }))
+    }
+
+    pub(crate) fn pending(&self) -> Arc<PendingEtags> {
+        self.pending.clone()
+    }
+
+    fn flush_pending(&self) {
+        if self.pending.has_pending.swap(false, Ordering::Acquire) {
+            let etags = mem::replace(&mut *self.pending.etags.lock().unwrap(), Vec::new());
+
+            for (path, cache) in etags {
+                self.insert_entry(path, Arc::new(cache));
+            }
+        }
+    }
+
+    /// Get a snapshot of the counters.
+    pub fn stats(&self) -> EtagStats {
+        EtagStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            not_modified: self.not_modified.load(Ordering::Relaxed),
+            hashing_time: *self.hashing_time.lock().unwrap(),
+            body_bytes: self.body_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_miss(&self, hashing_time: Duration) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        *self.hashing_time.lock().unwrap() += hashing_time;
+    }
+
+    pub(crate) fn record_not_modified(&self) {
+        self.not_modified.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the lock which should be held while computing the etag of a path, so that concurrent requests for the same path compute it only once.
+    pub(crate) fn computing(&self, path: &Path) -> Arc<Mutex<()>> {
+        self.computing.lock().unwrap().entry(path.to_path_buf()).or_insert_with(|| Arc::new(Mutex::new(()))).clone()
+    }
+
+    /// Release the computing lock of a path after its etag has been cached.
+    pub(crate) fn computed(&self, path: &Path) {
+        self.computing.lock().unwrap().remove(path);
+    }
+}
+
+impl Drop for EtagMap {
+    fn drop(&mut self) {
+        // a panic while saving would abort a thread which is already unwinding
+        if thread::panicking() {
+            return;
+        }
+
+        if let Some((ref path, _)) = self.persist {
+            let _ = self.save(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(etag: &str) -> EtagCache {
+        EtagCache {
+            etag: etag.to_string(),
+            algorithm: "crc64ecma",
+            weak: false,
+            size: 0,
+            modified: None,
+            content_type: None,
+        }
+    }
+
+    #[test]
+    fn evicts_the_entry_not_used_since_the_last_pass() {
+        let etag_map = EtagMap::with_capacity(2);
+
+        etag_map.insert(PathBuf::from("/a"), cache("A"));
+        etag_map.insert(PathBuf::from("/b"), cache("B"));
+
+        assert!(etag_map.get(Path::new("/a")).is_some());
+
+        etag_map.insert(PathBuf::from("/c"), cache("C"));
+
+        assert!(etag_map.get(Path::new("/a")).is_some());
+        assert!(etag_map.get(Path::new("/b")).is_none());
+        assert!(etag_map.get(Path::new("/c")).is_some());
+    }
+
+    #[test]
+    fn replacing_an_entry_does_not_evict_another() {
+        let etag_map = EtagMap::with_capacity(2);
+
+        etag_map.insert(PathBuf::from("/a"), cache("A"));
+        etag_map.insert(PathBuf::from("/b"), cache("B"));
+
+        for _ in 0..10 {
+            etag_map.insert(PathBuf::from("/a"), cache("A2"));
+        }
+
+        assert_eq!("A2", etag_map.get(Path::new("/a")).unwrap().etag);
+        assert!(etag_map.get(Path::new("/b")).is_some());
+        assert!(etag_map.entries.read().unwrap().ring.len() <= 4);
+    }
+
+    #[test]
+    fn a_persisted_map_is_saved_when_dropped() {
+        let path = ::std::env::temp_dir().join(format!("rocket-etagged-file-response-{}-persisted", ::std::process::id()));
+
+        {
+            let etag_map = EtagMap::new().persist(&path, Duration::from_secs(3600));
+
+            // the first etag is saved at once and the second one is only saved on drop
+            etag_map.insert(PathBuf::from("/a"), cache("A"));
+            etag_map.insert(PathBuf::from("/b"), cache("B"));
+        }
+
+        let etag_map = EtagMap::load(&path).unwrap();
+
+        assert_eq!("A", etag_map.get(Path::new("/a")).unwrap().etag);
+        assert_eq!("B", etag_map.get(Path::new("/b")).unwrap().etag);
+    }
+
+    #[test]
+    fn lookups_do_not_need_exclusive_access() {
+        let etag_map = Arc::new(EtagMap::new());
+
+        etag_map.insert(PathBuf::from("/a"), cache("A"));
+
+        // a lookup taking the write lock would wait for this read lock until the timeout
+        let _entries = etag_map.entries.read().unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..4 {
+            let etag_map = etag_map.clone();
+            let sender = sender.clone();
+
+            thread::spawn(move || {
+                let _ = sender.send(etag_map.get(Path::new("/a")).map(|cache| cache.etag.clone()));
+            });
+        }
+
+        for _ in 0..4 {
+            assert_eq!(Some("A".to_string()), receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+use std::path::{Path, PathBuf};
+
+use rocket::Rocket;
+use rocket::fairing::{Fairing, Info, Kind};
+
+use {EtagMap, EtaggedFileDefaults};
+
+/// The fairing which lets a rocket instance manage an `EtagMap`, so that the `State<EtagMap>` guard never fails because of a forgotten `manage` call. It also carries the defaults of the responses created with the map.
+///
+/// ```ignore
+/// rocket::ignite()
+///     .attach(EtagFairing::default().capacity(1024).cache_control("max-age=3600"))
+///     .mount("/", routes![file])
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct EtagFairing {
+    capacity: Option<usize>,
+    ttl: Option<Duration>,
+    body_cache: Option<(u64, usize)>,
+    load: Option<PathBuf>,
+    persist: Option<(PathBuf, Duration)>,
+    defaults: EtaggedFileDefaults,
+}
+
+impl EtagFairing {
+    /// Create a fairing managing an unbounded `EtagMap` whose entries never expire.
+    pub fn new() -> EtagFairing {
+        EtagFairing::default()
+    }
+
+    /// Hold at most `capacity` entries in the managed `EtagMap`.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+
+        self
+    }
+
+    /// Expire the entries of the managed `EtagMap` after `ttl`.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+
+        self
+    }
+
+    /// Cache the bodies of files up to `max_file_size` bytes in the managed `EtagMap`, taking at most `budget` bytes. See `EtagMap::body_cache`.
+    pub fn body_cache(mut self, max_file_size: u64, budget: usize) -> Self {
+        self.body_cache = Some((max_file_size, budget));
+
+        self
+    }
+
+    /// Load the etags saved by `EtagMap::save` into the managed `EtagMap` when the rocket instance is attached. A file which cannot be loaded, such as a missing one on the first start, is ignored.
+    pub fn load<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.load = Some(path.as_ref().to_path_buf());
+
+        self
+    }
+
+    /// Load the etags saved at `path` into the managed `EtagMap` when the rocket instance is attached, like `load`, and save them there every `interval` and when the map is dropped. See `EtagMap::persist`.
+    pub fn persist<P: AsRef<Path>>(mut self, path: P, interval: Duration) -> Self {
+        self.persist = Some((path.as_ref().to_path_buf(), interval));
+
+        self
+    }
+
+    /// Set the default `Cache-Control` header.
+    pub fn cache_control<S: Into<String>>(mut self, cache_control: S) -> Self {
+        self.defaults.cache_control = Some(cache_control.into());
+
+        self
+    }
+
+    /// Set whether to append `; charset=utf-8` to guessed textual content types by default.
+    pub fn utf8_charset(mut self, utf8_charset: bool) -> Self {
+        self.defaults.utf8_charset = utf8_charset;
+
+        self
+    }
+
+    /// Set whether to emit `X-Content-Type-Options: nosniff` by default.
+    pub fn nosniff(mut self, nosniff: bool) -> Self {
+        self.defaults.nosniff = nosniff;
+
+        self
+    }
+
+    /// Prepend a namespace, such as a deployment ID, to computed etags by default.
+    pub fn etag_prefix<S: Into<String>>(mut self, etag_prefix: S) -> Self {
+        self.defaults.etag_prefix = Some(etag_prefix.into());
+
+        self
+    }
+}
+
+impl Fairing for EtagFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "EtagMap",
+            kind: Kind::Attach,
+        }
+    }
+
+    fn on_attach(&self, rocket: Rocket) -> Result<Rocket, Rocket> {
+        let etag_map = EtagMap::with_options(self.capacity, self.ttl, self.defaults.clone());
+
+        let etag_map = match self.body_cache {
+            Some((max_file_size, budget)) => etag_map.body_cache(max_file_size, budget),
+            None => etag_map
+        };
+
+        if let Some(ref path) = self.load {
+            // the saved etags only spare hashing, so the rocket instance should launch without them
+            let _ = etag_map.load_into(path);
+        }
+
+        let etag_map = match self.persist {
+            Some((ref path, interval)) => {
+                if self.load.as_ref() != Some(path) {
+                    let _ = etag_map.load_into(path);
+                }
+
+                etag_map.persist(path, interval)
+            }
+            None => etag_map
+        };
+
+        Ok(rocket.manage(etag_map))
+    }
+}
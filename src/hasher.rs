@@ -0,0 +1,114 @@
+use crc_any::CRC;
+
+#[cfg(feature = "sha256")]
+use sha2::{Sha256, Digest};
+
+#[cfg(feature = "sha256")]
+use base64;
+
+#[cfg(feature = "blake3")]
+use blake3;
+
+/// The algorithm used for computing etags from the content of files.
+pub trait EtagHasher: Default + 'static {
+    /// A unique name of the algorithm. Cached etags computed by another algorithm are not reused.
+    const ALGORITHM: &'static str;
+
+    /// The name of the algorithm in the `Digest` header, if the etag is the base64-encoded digest of the content.
+    const DIGEST_ALGORITHM: Option<&'static str> = None;
+
+    /// Feed data into the hasher.
+    fn update(&mut self, data: &[u8]);
+
+    /// Consume the hasher and produce the etag.
+    fn finalize(self) -> String;
+}
+
+/// The default hasher, which computes CRC64-ECMA and formats it in uppercase hex.
+pub struct Crc64Hasher {
+    crc64ecma: CRC,
+}
+
+impl Default for Crc64Hasher {
+    fn default() -> Self {
+        Crc64Hasher {
+            crc64ecma: CRC::crc64ecma()
+        }
+    }
+}
+
+impl EtagHasher for Crc64Hasher {
+    const ALGORITHM: &'static str = "crc64ecma";
+
+    fn update(&mut self, data: &[u8]) {
+        self.crc64ecma.digest(data);
+    }
+
+    fn finalize(self) -> String {
+        let crc64 = self.crc64ecma.get_crc();
+
+        format!("{:X}", crc64)
+    }
+}
+
+/// The hasher which computes SHA-256 and formats it in base64, so that the etag can also be used in the `Digest` header.
+#[cfg(feature = "sha256")]
+#[derive(Default)]
+pub struct Sha256Hasher {
+    sha256: Sha256,
+}
+
+#[cfg(feature = "sha256")]
+impl EtagHasher for Sha256Hasher {
+    const ALGORITHM: &'static str = "sha-256";
+
+    const DIGEST_ALGORITHM: Option<&'static str> = Some("sha-256");
+
+    fn update(&mut self, data: &[u8]) {
+        self.sha256.input(data);
+    }
+
+    fn finalize(self) -> String {
+        base64::encode(&self.sha256.result())
+    }
+}
+
+/// The hasher which computes BLAKE3 and formats it in lowercase hex. It is cryptographically strong like SHA-256 but much faster.
+#[cfg(feature = "blake3")]
+pub struct Blake3Hasher {
+    blake3: blake3::Hasher,
+}
+
+#[cfg(feature = "blake3")]
+impl Default for Blake3Hasher {
+    fn default() -> Self {
+        Blake3Hasher {
+            blake3: blake3::Hasher::new()
+        }
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl EtagHasher for Blake3Hasher {
+    const ALGORITHM: &'static str = "blake3";
+
+    fn update(&mut self, data: &[u8]) {
+        self.blake3.update(data);
+    }
+
+    fn finalize(self) -> String {
+        self.blake3.finalize().to_hex().to_string()
+    }
+}
+
+/// Get the static name of a built-in algorithm by its name.
+pub(crate) fn algorithm(name: &str) -> Option<&'static str> {
+    match name {
+        name if name == Crc64Hasher::ALGORITHM => Some(Crc64Hasher::ALGORITHM),
+        #[cfg(feature = "sha256")]
+        name if name == Sha256Hasher::ALGORITHM => Some(Sha256Hasher::ALGORITHM),
+        #[cfg(feature = "blake3")]
+        name if name == Blake3Hasher::ALGORITHM => Some(Blake3Hasher::ALGORITHM),
+        _ => None
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use etag_map::PendingEtags;
+
+use {EtagHasher, EtagCache};
+
+/// A reader which hashes the data passing through it, and caches the etag once the whole file has been read. It lets the body of a file be sent while its etag is being computed.
+pub(crate) struct HashingReader<R: Read, H: EtagHasher> {
+    reader: R,
+    hasher: Option<H>,
+    read: u64,
+    path: PathBuf,
+    cache: EtagCache,
+    pending: Option<Arc<PendingEtags>>,
+}
+
+impl<R: Read, H: EtagHasher> HashingReader<R, H> {
+    /// The `cache` is the entry to be cached without its etag, and `pending` is where to put it when the etag has been computed.
+    pub(crate) fn new(reader: R, path: PathBuf, cache: EtagCache, pending: Option<Arc<PendingEtags>>) -> HashingReader<R, H> {
+        HashingReader {
+            reader,
+            hasher: Some(H::default()),
+            read: 0,
+            path,
+            cache,
+            pending,
+        }
+    }
+}
+
+impl<R: Read, H: EtagHasher> HashingReader<R, H> {
+    fn finish(&mut self) {
+        if let Some(hasher) = self.hasher.take() {
+            // a file which has been changed while being read is not cached
+            if self.read == self.cache.size {
+                if let Some(ref pending) = self.pending {
+                    let mut cache = self.cache.clone();
+
+                    cache.etag = hasher.finalize();
+
+                    pending.push(self.path.clone(), cache);
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read, H: EtagHasher> Read for HashingReader<R, H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let c = self.reader.read(buf)?;
+
+        if let Some(ref mut hasher) = self.hasher {
+            hasher.update(&buf[0..c]);
+
+            self.read += c as u64;
+        }
+
+        // a sized body stops reading at its `Content-Length`, so the end of the file may never be reached
+        if c == 0 || self.read >= self.cache.size {
+            self.finish();
+        }
+
+        Ok(c)
+    }
+}
@@ -0,0 +1,43 @@
+use rocket::Outcome;
+use rocket::request::{self, Request, FromRequest};
+use rocket::http::hyper::header::EntityTag;
+
+use IfNoneMatch;
+
+/// The request guard used for getting the `If-Match` header. It supports a comma-separated list of etags and the special value `*`. An `EtaggedFileResponse` checks it by itself, so the guard is only needed to inspect the header.
+#[derive(Debug, Clone, Default)]
+pub struct IfMatch {
+    /// Whether the header is `*`.
+    pub any: bool,
+    pub etags: Vec<EntityTag>,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfMatch {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let if_match = match request.headers().get_one("If-Match") {
+            Some(value) => IfMatch::parse(value),
+            None => IfMatch::default()
+        };
+
+        Outcome::Success(if_match)
+    }
+}
+
+impl IfMatch {
+    /// Parse the value of an `If-Match` header, which has the same syntax as `If-None-Match`. Malformed entity tags are skipped.
+    pub fn parse(value: &str) -> IfMatch {
+        let if_none_match = IfNoneMatch::parse(value);
+
+        IfMatch {
+            any: if_none_match.any,
+            etags: if_none_match.etags,
+        }
+    }
+
+    /// Whether the given etag of an existing resource matches this header. The strong comparison is used as RFC 7232 requires for `If-Match`, so a weak etag never matches, except for `*`.
+    pub fn matches(&self, etag: &str, weak: bool) -> bool {
+        self.any || (!weak && !etag.is_empty() && self.etags.iter().any(|r_etag| !r_etag.weak && r_etag.tag().eq(etag)))
+    }
+}
@@ -0,0 +1,37 @@
+use std::time::SystemTime;
+
+use rocket::Outcome;
+use rocket::request::{self, Request, FromRequest};
+use rocket::http::hyper::header::HttpDate;
+
+/// The request guard used for getting the `If-Modified-Since` header.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IfModifiedSince {
+    pub date: Option<SystemTime>
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfModifiedSince {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let date = request.headers().get_one("If-Modified-Since").and_then(|date| date.parse::<HttpDate>().ok()).map(SystemTime::from);
+
+        Outcome::Success(IfModifiedSince {
+            date
+        })
+    }
+}
+
+impl IfModifiedSince {
+    /// Whether a resource modified at `last_modified` has not been modified since the date in the header. HTTP dates only have a precision of seconds.
+    pub fn is_not_modified(&self, last_modified: SystemTime) -> bool {
+        match self.date {
+            Some(date) => {
+                let to_secs = |time: SystemTime| time.duration_since(::std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+                to_secs(last_modified) <= to_secs(date)
+            }
+            None => false
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use rocket::Outcome;
+use rocket::request::{self, Request, FromRequest};
+use rocket::http::hyper::header::EntityTag;
+
+use rocket_etag_if_none_match::EtagIfNoneMatch;
+
+/// The request guard used for getting the `If-None-Match` header. It supports a comma-separated list of etags and the special value `*`.
+#[derive(Debug, Clone, Default)]
+pub struct IfNoneMatch {
+    /// Whether the header is `*`.
+    pub any: bool,
+    pub etags: Vec<EntityTag>,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfNoneMatch {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let if_none_match = match request.headers().get_one("If-None-Match") {
+            Some(value) => IfNoneMatch::parse(value),
+            None => IfNoneMatch::default()
+        };
+
+        Outcome::Success(if_none_match)
+    }
+}
+
+impl From<EtagIfNoneMatch> for IfNoneMatch {
+    fn from(etag_if_none_match: EtagIfNoneMatch) -> Self {
+        IfNoneMatch {
+            any: false,
+            etags: etag_if_none_match.etag.into_iter().collect(),
+        }
+    }
+}
+
+impl IfNoneMatch {
+    /// Parse the value of an `If-None-Match` header. Malformed entity tags are skipped.
+    pub fn parse(value: &str) -> IfNoneMatch {
+        let value = value.trim();
+
+        if value == "*" {
+            return IfNoneMatch {
+                any: true,
+                etags: Vec::new(),
+            };
+        }
+
+        let mut etags = Vec::new();
+
+        let mut rest = value;
+
+        loop {
+            rest = rest.trim_left_matches(|c: char| c == ',' || c.is_whitespace());
+
+            if rest.is_empty() {
+                break;
+            }
+
+            let weak = rest.starts_with("W/");
+
+            if weak {
+                rest = &rest[2..];
+            }
+
+            if !rest.starts_with('"') {
+                // skip a malformed entity tag
+                match rest.find(',') {
+                    Some(index) => {
+                        rest = &rest[index..];
+                        continue;
+                    }
+                    None => break
+                }
+            }
+
+            match rest[1..].find('"') {
+                Some(index) => {
+                    etags.push(EntityTag::new(weak, rest[1..(index + 1)].to_string()));
+
+                    rest = &rest[(index + 2)..];
+                }
+                None => break
+            }
+        }
+
+        IfNoneMatch {
+            any: false,
+            etags,
+        }
+    }
+
+    /// Whether the given etag of an existing resource matches this header. The weak comparison is used as RFC 7232 requires for `If-None-Match`, so whether either etag is weak (`W/`) does not matter. An empty etag, which a response without one has, never matches.
+    pub fn matches(&self, etag: &str) -> bool {
+        !etag.is_empty() && (self.any || self.etags.iter().any(|r_etag| r_etag.tag().eq(etag)))
+    }
+}
@@ -8,157 +8,1165 @@ pub extern crate rocket_etag_if_none_match;
 
 extern crate rocket;
 
-use std::sync::Mutex;
-use std::collections::HashMap;
-use std::path::Path;
-use std::fs::{self, File};
-use std::io::{self, ErrorKind, Read, BufReader};
+#[cfg(feature = "gzip")]
+extern crate flate2;
 
-use mime_guess::get_mime_type_str;
+#[cfg(feature = "brotli")]
+extern crate brotli_crate as brotli;
 
-use crc_any::CRC;
+#[cfg(feature = "sha256")]
+extern crate sha2;
 
-use rocket_etag_if_none_match::EtagIfNoneMatch;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+
+#[cfg(feature = "blake3")]
+extern crate blake3_crate as blake3;
 
-use rocket::response::{self, Response, Responder};
-use rocket::http::{Status, hyper::header::{ETag, EntityTag}};
+#[cfg(feature = "sha256")]
+extern crate base64;
+
+mod range;
+mod if_modified_since;
+mod if_none_match;
+mod if_match;
+mod disposition;
+mod hasher;
+mod accept_encoding;
+mod compression;
+mod etag_map;
+mod builder;
+mod content_type;
+mod fairing;
+mod error;
+mod mmap;
+mod hashing_reader;
+mod options;
+mod multipart;
+mod defaults;
+mod sized_body;
+mod cache_control;
+mod shared_bytes;
+mod rate_limit;
+mod background;
+
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Cursor};
+use std::time::{SystemTime, Instant, Duration, UNIX_EPOCH};
+use std::cell::RefCell;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+use rocket::response::{self, Response, ResponseBuilder, Responder, Body};
+use rocket::http::{Status, Method, hyper::header::{ETag, EntityTag, HttpDate}};
 use rocket::request::{Request, State};
 
+use rocket_etag_if_none_match::EtagIfNoneMatch;
+
+use range::ByteRange;
+use multipart::MultipartByteranges;
+use sized_body::SizedBody;
+use etag_map::PendingEtags;
+use shared_bytes::SharedBytes;
+use rate_limit::RateLimited;
+use background::HashJob;
+
+pub use if_modified_since::IfModifiedSince;
+pub use if_none_match::IfNoneMatch;
+pub use if_match::IfMatch;
+pub use hasher::{EtagHasher, Crc64Hasher};
+
+#[cfg(feature = "sha256")]
+pub use hasher::Sha256Hasher;
+
+#[cfg(feature = "blake3")]
+pub use hasher::Blake3Hasher;
+pub use accept_encoding::AcceptEncoding;
+pub use compression::Compression;
+pub use etag_map::{EtagMap, EtagCache, EtagStats, EtagSource};
+pub use builder::EtaggedFileResponseBuilder;
+pub use fairing::EtagFairing;
+pub use error::EtaggedFileError;
+pub use options::EtaggedFileOptions;
+pub use defaults::EtaggedFileDefaults;
+pub use cache_control::CacheControl;
+
 const FILE_RESPONSE_CHUNK_SIZE: u64 = 4096;
 
-/// This map should be managed by a rocket instance.
-pub type EtagMap = Mutex<HashMap<String, String>>;
+/// Content codings of precompressed files and the extensions of them, in order of preference between equal quality values.
+const PRECOMPRESSED_ENCODINGS: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// The largest hashing buffer kept by a worker thread between cache misses.
+const MAX_RETAINED_HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The most etags of shared data kept by a worker thread. Each of them keeps the allocation of its data alive until the data are dropped and the next call forgets it.
+const MAX_SHARED_ETAGS: usize = 256;
+
+/// The `Cache-Control` header for fingerprinted assets which never change.
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+thread_local! {
+    /// The etags of static data, keyed by the address and the length of the data.
+    static EMBEDDED_ETAGS: RefCell<HashMap<(usize, usize), String>> = RefCell::new(HashMap::new());
+
+    /// The etags of shared data, keyed by the address and the length of the data. The weak reference tells whether the address still belongs to the same data. It holds at most `MAX_SHARED_ETAGS` entries.
+    static SHARED_ETAGS: RefCell<HashMap<(usize, usize), (Weak<[u8]>, String)>> = RefCell::new(HashMap::new());
+
+    /// The buffer for hashing files, reused by the requests handled by the same worker thread unless it is larger than `MAX_RETAINED_HASH_BUFFER_SIZE`.
+    static HASH_BUFFER: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+}
+
+/// The metadata of a file to be served, and whether to memory-map it.
+#[derive(Debug, Clone, Copy)]
+struct FileInfo {
+    size: u64,
+    modified: Option<SystemTime>,
+    mmap: bool,
+}
+
+impl FileInfo {
+    fn new(metadata: &fs::Metadata, mmap: bool) -> FileInfo {
+        FileInfo {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            mmap,
+        }
+    }
+}
+
+/// A readable and seekable data source.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// The body of an `EtaggedFileResponse`. Only a seekable body can be served partially for range requests.
+pub enum EtaggedFileData {
+    Seekable(Box<ReadSeek>),
+    Reader(Box<Read>),
+}
 
 /// The response struct used for offering static files with **Etag** cache.
+///
+/// Rocket already implements `Responder` for `Option<EtaggedFileResponse>`, where `None` is responded with `404 Not Found`, so a route can return it directly.
+///
+/// A HEAD request gets the same headers as a GET request, including the true `Content-Length`, but no body. Rocket 0.3 dispatches a HEAD request without a `#[head]` route to the GET route and strips the body itself, so the file is still opened for it. Only a `#[head]` route spares the body from being read.
 pub struct EtaggedFileResponse {
-    pub data: Option<Box<Read>>,
+    pub data: Option<EtaggedFileData>,
     pub is_etag_match: bool,
     pub etag: String,
+    pub weak: bool,
     pub content_type: Option<String>,
     pub content_length: Option<u64>,
+    pub last_modified: Option<SystemTime>,
+    pub content_disposition: Option<String>,
+    pub cache_control: Option<String>,
+    pub content_encoding: Option<String>,
+    pub vary: Option<String>,
+    pub nosniff: bool,
+    pub digest: Option<String>,
+    pub expires: Option<Duration>,
+    pub cors_origin: Option<String>,
+    pub compression: Option<Compression>,
+    pub chunk_size: u64,
+    pub etag_source: EtagSource,
+    pub age: Option<Duration>,
+    pub rate_limit: Option<u64>,
+    pub cross_origin_resource_policy: Option<String>,
+    pub cross_origin_embedder_policy: Option<String>,
+    pub content_language: Option<String>,
 }
 
 impl<'a> Responder<'a> for EtaggedFileResponse {
-    fn respond_to(self, _: &Request) -> response::Result<'a> {
+    fn respond_to(self, request: &Request) -> response::Result<'a> {
         let mut response = Response::build();
 
-        if self.is_etag_match {
+        let now = SystemTime::now();
+
+        response.raw_header("Date", HttpDate::from(now).to_string());
+
+        if let Some(age) = self.age {
+            response.raw_header("Age", age.as_secs().to_string());
+        }
+
+        // a `max-age` too large for the clock has no date to expire at
+        if let Some(expires) = self.expires.and_then(|expires| now.checked_add(expires)) {
+            response.raw_header("Expires", HttpDate::from(expires).to_string());
+        }
+
+        if let Some(last_modified) = self.last_modified {
+            response.raw_header("Last-Modified", HttpDate::from(last_modified).to_string());
+        }
+
+        if let Some(cache_control) = self.cache_control {
+            response.raw_header("Cache-Control", cache_control);
+        }
+
+        if let Some(vary) = self.vary {
+            response.raw_header("Vary", vary);
+        }
+
+        if self.nosniff {
+            response.raw_header("X-Content-Type-Options", "nosniff");
+        }
+
+        if let Some(cross_origin_resource_policy) = self.cross_origin_resource_policy {
+            response.raw_header("Cross-Origin-Resource-Policy", cross_origin_resource_policy);
+        }
+
+        if let Some(cross_origin_embedder_policy) = self.cross_origin_embedder_policy {
+            response.raw_header("Cross-Origin-Embedder-Policy", cross_origin_embedder_policy);
+        }
+
+        if let Some(cors_origin) = self.cors_origin {
+            response.raw_header("Access-Control-Allow-Origin", cors_origin);
+
+            response.raw_header("Access-Control-Allow-Methods", "GET, HEAD, OPTIONS");
+        }
+
+        // `If-Match` is evaluated before `If-None-Match`, so a client coordinating on a version never gets another one
+        // an etag which is still being computed cannot be evaluated, so the response is served rather than refused for a version it may well have
+        let is_precondition_failed = match request.headers().get_one("If-Match") {
+            Some(_) if self.etag_source == EtagSource::Deferred => false,
+            Some(if_match) => !IfMatch::parse(if_match).matches(&self.etag, self.weak),
+            None => false
+        };
+
+        if is_precondition_failed {
+            response.status(Status::PreconditionFailed);
+
+            if !self.etag.is_empty() {
+                response.header(ETag(EntityTag::new(self.weak, self.etag)));
+            }
+
+            response.raw_header("Content-Length", "0");
+        } else if self.is_etag_match {
             response.status(Status::NotModified);
-        } else {
-            response.header(ETag(EntityTag::new(true, self.etag.clone())));
 
-            if let Some(content_type) = self.content_type {
-                response.raw_header("Content-Type", content_type);
+            if !self.etag.is_empty() {
+                response.header(ETag(EntityTag::new(self.weak, self.etag)));
             }
 
+            // a `Content-Length` on a 304 must be the length of the body a 200 would have, since caches merge it into the stored response
             if let Some(content_length) = self.content_length {
                 response.raw_header("Content-Length", content_length.to_string());
             }
+        } else {
+            let data = self.data.unwrap();
+
+            // only a seekable body which is not compressed on the fly can be served partially
+            let range = match (&data, self.content_length, self.compression) {
+                (&EtaggedFileData::Seekable(_), Some(content_length), None) => {
+                    // a range of a resource which has been changed since `If-Range` would corrupt a resumed download, so the whole resource is sent instead
+                    let is_unchanged = match request.headers().get_one("If-Range") {
+                        Some(if_range) => range::if_range_matches(if_range, &self.etag, self.weak, self.last_modified),
+                        None => true
+                    };
+
+                    if is_unchanged {
+                        request.headers().get_one("Range").map(|range| ByteRange::parse(range, content_length))
+                    } else {
+                        None
+                    }
+                }
+                _ => None
+            };
+
+            if let Some(ByteRange::Unsatisfiable) = range {
+                response.status(Status::RangeNotSatisfiable);
+
+                // the length lets the client retry with a range inside the resource
+                response.raw_header("Content-Range", format!("bytes */{}", self.content_length.unwrap()));
+
+                response.raw_header("Accept-Ranges", "bytes");
+
+                response.raw_header("Content-Length", "0");
+
+                return response.ok();
+            }
+
+            if !self.etag.is_empty() {
+                response.header(ETag(EntityTag::new(self.weak, self.etag)));
+            }
+
+            if let Some(ref content_type) = self.content_type {
+                response.raw_header("Content-Type", content_type.clone());
+            }
+
+            if let Some(content_language) = self.content_language {
+                response.raw_header("Content-Language", content_language);
+            }
+
+            if let Some(content_disposition) = self.content_disposition {
+                response.raw_header("Content-Disposition", content_disposition);
+            }
+
+            if let Some(content_encoding) = self.content_encoding {
+                response.raw_header("Content-Encoding", content_encoding);
+            }
+
+            if let Some(digest) = self.digest {
+                response.raw_header("Digest", digest);
+            }
+
+            let (body, length): (Box<Read>, Option<u64>) = match (data, range) {
+                (EtaggedFileData::Seekable(mut data), Some(ByteRange::Satisfiable(start, end))) => {
+                    let length = end - start + 1;
+
+                    if data.seek(SeekFrom::Start(start)).is_err() {
+                        return Err(Status::InternalServerError);
+                    }
+
+                    response.status(Status::PartialContent);
+
+                    response.raw_header("Content-Range", format!("bytes {}-{}/{}", start, end, self.content_length.unwrap()));
+
+                    (Box::new(data.take(length)), Some(length))
+                }
+                (EtaggedFileData::Seekable(data), Some(ByteRange::Multiple(ranges))) => {
+                    let (body, length, boundary) = MultipartByteranges::new(data, &ranges, self.content_length.unwrap(), self.content_type.as_ref().map(|content_type| content_type.as_str()));
+
+                    response.status(Status::PartialContent);
+
+                    response.raw_header("Content-Type", format!("multipart/byteranges; boundary={}", boundary));
+
+                    (Box::new(body), Some(length))
+                }
+                (EtaggedFileData::Seekable(data), _) => {
+                    match self.compression {
+                        Some(compression) => (compression.encode(data), None),
+                        None => {
+                            response.raw_header("Accept-Ranges", "bytes");
+
+                            (Box::new(data), self.content_length)
+                        }
+                    }
+                }
+                (EtaggedFileData::Reader(data), _) => {
+                    match self.compression {
+                        Some(compression) => (compression.encode(data), None),
+                        None => (data, self.content_length)
+                    }
+                }
+            };
 
-            response.chunked_body(self.data.unwrap(), FILE_RESPONSE_CHUNK_SIZE);
+            let body: Box<Read> = match self.rate_limit {
+                Some(rate_limit) if rate_limit > 0 => Box::new(RateLimited::new(body, rate_limit)),
+                _ => body
+            };
+
+            // the method is only HEAD for a `#[head]` route, since Rocket turns a HEAD request for a GET route into a GET request and strips the body afterwards
+            set_body(&mut response, body, length, self.chunk_size, request.method() == Method::Head);
         }
 
         response.ok()
     }
 }
 
+/// Get the `max-age` directive of a `Cache-Control` header.
+fn max_age(cache_control: &str) -> Option<u64> {
+    cache_control.split(',').filter_map(|directive| {
+        let directive = directive.trim();
+
+        match directive.get(..8) {
+            Some(name) if name.eq_ignore_ascii_case("max-age=") => directive[8..].trim_matches('"').parse().ok(),
+            _ => None
+        }
+    }).next()
+}
+
+/// Use a sized body if the length is known, or a chunked body otherwise. The data is not read for a HEAD request.
+fn set_body(response: &mut ResponseBuilder, data: Box<Read>, content_length: Option<u64>, chunk_size: u64, is_head: bool) {
+    match content_length {
+        Some(content_length) => {
+            response.raw_header("Content-Length", content_length.to_string());
+
+            if is_head {
+                response.raw_body(Body::Sized(io::empty(), content_length));
+            } else {
+                response.raw_body(Body::Sized(SizedBody::new(data, content_length), content_length));
+            }
+        }
+        None => {
+            if !is_head {
+                response.chunked_body(data, chunk_size);
+            }
+        }
+    }
+}
+
+/// The responder wrapping the result of creating an `EtaggedFileResponse` instance. The error is responded the way `EtaggedFileError` does.
+pub struct EtaggedFileResult(pub Result<EtaggedFileResponse, EtaggedFileError>);
+
+impl From<Result<EtaggedFileResponse, EtaggedFileError>> for EtaggedFileResult {
+    fn from(result: Result<EtaggedFileResponse, EtaggedFileError>) -> Self {
+        EtaggedFileResult(result)
+    }
+}
+
+impl From<Option<EtaggedFileResponse>> for EtaggedFileResult {
+    fn from(response: Option<EtaggedFileResponse>) -> Self {
+        EtaggedFileResult(response.ok_or(EtaggedFileError::NotFound))
+    }
+}
+
+impl From<io::Result<EtaggedFileResponse>> for EtaggedFileResult {
+    fn from(result: io::Result<EtaggedFileResponse>) -> Self {
+        EtaggedFileResult(result.map_err(EtaggedFileError::from))
+    }
+}
+
+impl<'a> Responder<'a> for EtaggedFileResult {
+    fn respond_to(self, request: &Request) -> response::Result<'a> {
+        match self.0 {
+            Ok(response) => response.respond_to(request),
+            Err(error) => error.respond_to(request)
+        }
+    }
+}
+
 impl EtaggedFileResponse {
-    /// Create a EtaggedFileResponse instance from a path of a file.
-    pub fn from<P: AsRef<Path>>(etag_map: State<EtagMap>, etag_if_none_match: EtagIfNoneMatch, path: P) -> io::Result<EtaggedFileResponse> {
-        let path = match path.as_ref().canonicalize() {
+    /// Create a EtaggedFileResponse instance from a path of a file. The response is `304 Not Modified` if the etag matches `If-None-Match`.
+    pub fn from<P: AsRef<Path>>(etag_map: State<EtagMap>, etag_if_none_match: EtagIfNoneMatch, path: P) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        Self::from_etag_map(&etag_map, IfNoneMatch::from(etag_if_none_match), IfModifiedSince::default(), path)
+    }
+
+    /// Create a EtaggedFileResponse instance from a path of a file like `from`, but with the `IfNoneMatch` guard, which also supports lists of etags and `*`, and the `IfModifiedSince` guard. The response is `304 Not Modified` if the etag matches `If-None-Match`, or, without `If-None-Match`, if the file has not been modified since `If-Modified-Since`.
+    pub fn from_conditional<P: AsRef<Path>>(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, if_modified_since: IfModifiedSince, path: P) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        Self::from_etag_map(&etag_map, if_none_match, if_modified_since, path)
+    }
+
+    /// Create a EtaggedFileResponse instance from a path of a file like `from_conditional`, but with a borrowed `EtagMap`, so that it can be used outside of a request, such as in tests or command-line tools.
+    pub fn from_etag_map<P: AsRef<Path>>(etag_map: &EtagMap, if_none_match: IfNoneMatch, if_modified_since: IfModifiedSince, path: P) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        EtaggedFileResponseBuilder::new_borrowed(path.as_ref()).etag_map(etag_map).if_modified_since(if_modified_since).build(if_none_match)
+    }
+
+    /// Create a EtaggedFileResponse instance from a path of a file. If the client accepts it, a precompressed sibling file (`path.br` or `path.gz`) is served instead with the content type of the original file. Brotli is preferred when both are accepted equally.
+    pub fn from_precompressed<P: AsRef<Path>>(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, if_modified_since: IfModifiedSince, accept_encoding: AcceptEncoding, path: P) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        EtaggedFileResponseBuilder::new_borrowed(path.as_ref()).etag_map(&etag_map).if_modified_since(if_modified_since).accept_encoding(accept_encoding).build(if_none_match)
+    }
+
+    /// Create a EtaggedFileResponse instance from a path of a file with a known content type, such as `text/html; charset=utf-8` for a fixed route. The content type is sent verbatim and never guessed from the extension.
+    pub fn from_with_content_type<P: AsRef<Path>, S: Into<String>>(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, if_modified_since: IfModifiedSince, path: P, content_type: S) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        EtaggedFileResponseBuilder::new_borrowed(path.as_ref()).etag_map(&etag_map).if_modified_since(if_modified_since).content_type(content_type).build(if_none_match)
+    }
+
+    /// Create a EtaggedFileResponse instance from a path of a file stored without an extension, such as under an opaque key, whose content type is guessed from `virtual_filename` instead. The filename is also sent in an inline `Content-Disposition` header.
+    pub fn from_with_virtual_filename<P: AsRef<Path>, S: Into<String>>(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, if_modified_since: IfModifiedSince, path: P, virtual_filename: S) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        EtaggedFileResponseBuilder::new_borrowed(path.as_ref()).etag_map(&etag_map).if_modified_since(if_modified_since).virtual_filename(virtual_filename).build(if_none_match)
+    }
+
+    /// Create a EtaggedFileResponse instance from a path of a file with a custom chunk size, which is used for both the hashing buffer and the streamed body. The default chunk size is 4096 bytes.
+    pub fn from_with_chunk_size<P: AsRef<Path>>(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, if_modified_since: IfModifiedSince, path: P, chunk_size: u64) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        EtaggedFileResponseBuilder::new_borrowed(path.as_ref()).etag_map(&etag_map).if_modified_since(if_modified_since).chunk_size(chunk_size).build(if_none_match)
+    }
+
+    /// Create a EtaggedFileResponse instance from a path of a file with a custom etag hasher.
+    pub fn from_with_hasher<H: EtagHasher, P: AsRef<Path>>(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, if_modified_since: IfModifiedSince, path: P) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        EtaggedFileResponseBuilder::new_borrowed(path.as_ref()).hasher::<H>().etag_map(&etag_map).if_modified_since(if_modified_since).build(if_none_match)
+    }
+
+    /// Create a EtaggedFileResponse instance from a path of a file with a weak etag. The weak etag is computed from the size and the modification time of the file, so the content of the file is never read for hashing.
+    pub fn from_weak<P: AsRef<Path>>(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, if_modified_since: IfModifiedSince, path: P) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        EtaggedFileResponseBuilder::new_borrowed(path.as_ref()).etag_map(&etag_map).if_modified_since(if_modified_since).weak_etag(true).build(if_none_match)
+    }
+
+    /// Create a EtaggedFileResponse instance from a file in a directory. The `relative` path is joined to `base`. If it targets a directory, the first existing file in `index_files` in that directory is served. Any path resolving outside `base` is treated as not found.
+    pub fn from_dir<P: AsRef<Path>, Q: AsRef<Path>>(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, if_modified_since: IfModifiedSince, base: P, relative: Q, index_files: &[&str]) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        let path = Self::resolve_in_dir(base.as_ref(), relative.as_ref(), index_files)?;
+
+        Self::from_conditional(etag_map, if_none_match, if_modified_since, path)
+    }
+
+    /// Create a EtaggedFileResponse instance from a file in a directory like `from_dir`, but a missing file is served with the `fallback` file, such as `index.html` of a single-page app, so that client-side routing works. The fallback has its own etag and its content type is guessed from its own extension.
+    pub fn from_dir_spa<P: AsRef<Path>, Q: AsRef<Path>, F: AsRef<Path>>(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, if_modified_since: IfModifiedSince, base: P, relative: Q, index_files: &[&str], fallback: F) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        let path = match Self::resolve_in_dir(base.as_ref(), relative.as_ref(), index_files) {
             Ok(path) => path,
-            Err(e) => Err(e)?
+            Err(ref error) if error.kind() == ErrorKind::NotFound => fallback.as_ref().to_path_buf(),
+            Err(error) => return Err(error.into())
         };
 
-        if !path.is_file() {
-            return Err(io::Error::from(ErrorKind::InvalidInput));
+        Self::from_conditional(etag_map, if_none_match, if_modified_since, path)
+    }
+
+    /// Create a EtaggedFileResponse instance from a file under a root directory. The `relative` path is joined to `root`, and `EtaggedFileError::NotFound` is returned if the resolved path escapes from `root`, such as `../../etc/passwd`.
+    pub fn from_rooted<P: AsRef<Path>, Q: AsRef<Path>>(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, if_modified_since: IfModifiedSince, root: P, relative: Q) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        let root = root.as_ref().canonicalize()?;
+
+        let path = root.join(relative).canonicalize()?;
+
+        if !path.starts_with(&root) {
+            return Err(EtaggedFileError::NotFound);
         }
 
-        let path_str = path.to_str().unwrap();
+        Self::from_conditional(etag_map, if_none_match, if_modified_since, path)
+    }
 
-        let etag = etag_map.lock().unwrap().get(path_str).map(|etag| { etag.clone() });
+    fn resolve_in_dir(base: &Path, relative: &Path, index_files: &[&str]) -> io::Result<PathBuf> {
+        let base = base.canonicalize()?;
 
-        let etag = match etag {
-            Some(etag) => etag,
-            None => {
-                let mut crc64ecma = CRC::crc64ecma();
+        let path = base.join(relative).canonicalize()?;
 
-                let mut buffer = [0u8; FILE_RESPONSE_CHUNK_SIZE as usize];
+        if !path.starts_with(&base) {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
 
-                let read = File::open(&path)?;
+        if path.is_dir() {
+            for index_file in index_files {
+                let index_path = path.join(index_file);
 
-                let mut reader = BufReader::new(read);
+                if index_path.is_file() {
+                    let index_path = index_path.canonicalize()?;
 
-                loop {
-                    match reader.read(&mut buffer) {
-                        Ok(c) => {
-                            if c == 0 {
-                                break;
-                            }
-                            crc64ecma.digest(&buffer[0..c]);
-                        }
-                        Err(error) => {
-                            return Err(error);
-                        }
+                    // the index file may be a symlink pointing outside
+                    if index_path.starts_with(&base) {
+                        return Ok(index_path);
                     }
                 }
+            }
+
+            Err(io::Error::from(ErrorKind::NotFound))
+        } else {
+            Ok(path)
+        }
+    }
+
+    /// Find the precompressed sibling of a file whose content coding has the highest quality value. The order of `PRECOMPRESSED_ENCODINGS` breaks ties.
+    fn find_precompressed<'p>(path: &'p Path, accept_encoding: &AcceptEncoding) -> (Cow<'p, Path>, Option<&'static str>) {
+        let mut found: Option<(PathBuf, &'static str, f32)> = None;
+
+        for &(encoding, extension) in PRECOMPRESSED_ENCODINGS.iter() {
+            let quality = accept_encoding.quality(encoding);
+
+            if quality > 0.0 && found.as_ref().map(|&(_, _, found_quality)| quality > found_quality).unwrap_or(true) {
+                let mut sibling = path.as_os_str().to_os_string();
+
+                sibling.push(".");
+                sibling.push(extension);
+
+                let sibling = PathBuf::from(sibling);
+
+                if sibling.is_file() {
+                    found = Some((sibling, encoding, quality));
+                }
+            }
+        }
+
+        match found {
+            Some((sibling, encoding, _)) => (Cow::Owned(sibling), Some(encoding)),
+            None => (Cow::Borrowed(path), None)
+        }
+    }
 
-                let crc64 = crc64ecma.get_crc();
+    /// Create a EtaggedFileResponse instance from a path of a file with an etag provided by the caller, such as a digest from a build manifest. The file is never read for hashing and the `EtagMap` is not involved.
+    pub fn from_with_etag<P: AsRef<Path>, S: Into<String>>(if_none_match: IfNoneMatch, path: P, etag: S) -> Result<EtaggedFileResponse, EtaggedFileError> {
+        EtaggedFileResponseBuilder::new_borrowed(path.as_ref()).etag(etag).build(if_none_match)
+    }
 
-                let etag = format!("{:X}", crc64);
+    fn build_file_response(path: &Path, content_type: Option<String>, file: Option<File>, info: FileInfo, etag: String, weak: bool, is_etag_match: bool) -> io::Result<EtaggedFileResponse> {
+        if is_etag_match {
+            Ok(Self::new_response(None, etag, weak, None, Some(info.size), info.modified))
+        } else {
+            let file = match file {
+                Some(file) => file,
+                None => File::open(&path)?
+            };
+
+            let mapped = if info.mmap { mmap::body(&file) } else { None };
+
+            let data = match mapped {
+                Some(data) => data,
+                None => Box::new(file)
+            };
 
-                let path_string = path_str.to_string();
+            Ok(Self::new_response(Some(EtaggedFileData::Seekable(data)), etag, weak, content_type, Some(info.size), info.modified))
+        }
+    }
+
+    /// Read the body of a small file from the body cache of the `EtagMap`, or read the file into it. Returns `None` if the file is not eligible, leaving `file` for the response body.
+    fn memory_body(etag_map: &EtagMap, path: &Path, file: &mut Option<File>, file_size: u64, last_modified: Option<SystemTime>) -> io::Result<Option<Box<ReadSeek>>> {
+        match etag_map.body_cache_max_file_size() {
+            Some(max_file_size) if file_size <= max_file_size => (),
+            _ => return Ok(None)
+        }
+
+        let body = match etag_map.get_body(path, file_size, last_modified) {
+            Some(cached) => cached,
+            None => {
+                let file = match file.take() {
+                    Some(file) => file,
+                    None => File::open(path)?
+                };
 
-                let cloned_etag = etag.clone();
+                let mut body = Vec::with_capacity(file_size as usize);
 
-                etag_map.lock().unwrap().insert(path_string, cloned_etag);
+                file.take(file_size).read_to_end(&mut body)?;
 
-                etag
+                let body = Arc::new(body);
+
+                // a file changed since it was stat'ed is served but not cached
+                if body.len() as u64 == file_size {
+                    etag_map.insert_body(path, file_size, last_modified, body.clone());
+                }
+
+                body
             }
         };
 
-        let is_etag_match = match etag_if_none_match.etag {
-            Some(r_etag) => r_etag.tag().eq(&etag),
-            None => false
+        Ok(Some(Box::new(Cursor::new(SharedBytes(body)))))
+    }
+
+    /// Create a EtaggedFileResponse instance from in-memory data. The etag is computed from the data every time, so the `EtagMap` is not involved. The data is seekable, so `Range` requests are served by slicing it, the same way as files.
+    ///
+    /// It goes through the same conditional logic as files, so it also lets the conditional behavior be tested without files on disk, with `IfNoneMatch::parse` and Rocket's local client.
+    ///
+    /// ```ignore
+    /// #[get("/data")]
+    /// fn data(if_none_match: IfNoneMatch) -> EtaggedFileResponse {
+    ///     EtaggedFileResponse::from_bytes(if_none_match, b"hello".to_vec(), Some("text/plain".to_string()))
+    /// }
+    ///
+    /// let client = Client::new(rocket::ignite().mount("/", routes![data])).unwrap();
+    ///
+    /// let etag = client.get("/data").dispatch().headers().get_one("ETag").unwrap().to_string();
+    ///
+    /// let response = client.get("/data").header(Header::new("If-None-Match", etag)).dispatch();
+    ///
+    /// assert_eq!(Status::NotModified, response.status());
+    /// ```
+    pub fn from_bytes(if_none_match: IfNoneMatch, data: Vec<u8>, content_type: Option<String>) -> EtaggedFileResponse {
+        let etag = Self::hash_bytes(&data);
+
+        let content_length = data.len() as u64;
+
+        if if_none_match.matches(&etag) {
+            Self::new_response(None, etag, false, None, Some(content_length), None)
+        } else {
+            Self::new_response(Some(EtaggedFileData::Seekable(Box::new(Cursor::new(data)))), etag, false, content_type, Some(content_length), None)
+        }
+    }
+
+    /// Create a EtaggedFileResponse instance from static data, such as an asset embedded by `include_bytes!`. The etag of the same slice is only computed once per worker thread. `Range` requests are served by slicing the data.
+    pub fn from_embedded(if_none_match: IfNoneMatch, data: &'static [u8], content_type: Option<String>) -> EtaggedFileResponse {
+        let key = (data.as_ptr() as usize, data.len());
+
+        let mut etag_source = EtagSource::CacheHit;
+
+        let etag = EMBEDDED_ETAGS.with(|embedded_etags| {
+            embedded_etags.borrow_mut().entry(key).or_insert_with(|| {
+                etag_source = EtagSource::Computed;
+
+                Self::hash_bytes(data)
+            }).clone()
+        });
+
+        let mut response = if if_none_match.matches(&etag) {
+            Self::new_response(None, etag, false, None, Some(data.len() as u64), None)
+        } else {
+            Self::new_response(Some(EtaggedFileData::Seekable(Box::new(Cursor::new(data)))), etag, false, content_type, Some(data.len() as u64), None)
         };
 
-        if is_etag_match {
-            Ok(EtaggedFileResponse {
-                data: None,
-                is_etag_match: true,
+        response.etag_source = etag_source;
+
+        response
+    }
+
+    /// Create a EtaggedFileResponse instance from data shared across requests, such as an asset loaded at startup, without copying it. The etag is the given one, or it is computed once per worker thread for the same `Arc`. `Range` requests are served by slicing the data.
+    pub fn from_shared(if_none_match: IfNoneMatch, data: Arc<[u8]>, content_type: Option<String>, etag: Option<String>) -> EtaggedFileResponse {
+        let (etag, etag_source) = match etag {
+            Some(etag) => (etag, EtagSource::Provided),
+            None => {
+                let key = (data.as_ptr() as usize, data.len());
+
+                SHARED_ETAGS.with(|shared_etags| {
+                    let mut shared_etags = shared_etags.borrow_mut();
+
+                    // a weak reference keeps the allocation of dropped data alive, and its address may be reused, so dropped data are forgotten on every call
+                    shared_etags.retain(|_, &mut (ref weak, _)| weak.upgrade().is_some());
+
+                    if let Some(&(ref weak, ref etag)) = shared_etags.get(&key) {
+                        if weak.upgrade().map(|cached| Arc::ptr_eq(&cached, &data)).unwrap_or(false) {
+                            return (etag.clone(), EtagSource::CacheHit);
+                        }
+                    }
+
+                    if shared_etags.len() >= MAX_SHARED_ETAGS {
+                        shared_etags.clear();
+                    }
+
+                    let etag = Self::hash_bytes(&data);
+
+                    shared_etags.insert(key, (Arc::downgrade(&data), etag.clone()));
+
+                    (etag, EtagSource::Computed)
+                })
+            }
+        };
+
+        let content_length = data.len() as u64;
+
+        let mut response = if if_none_match.matches(&etag) {
+            Self::new_response(None, etag, false, None, Some(content_length), None)
+        } else {
+            Self::new_response(Some(EtaggedFileData::Seekable(Box::new(Cursor::new(SharedBytes(data))))), etag, false, content_type, Some(content_length), None)
+        };
+
+        response.etag_source = etag_source;
+
+        response
+    }
+
+    fn hash_bytes(data: &[u8]) -> String {
+        let mut hasher = Crc64Hasher::default();
+
+        hasher.update(data);
+
+        hasher.finalize()
+    }
+
+    /// Create a EtaggedFileResponse instance from any reader, such as a decryption stream, with an etag provided by the caller. The reader is only consumed if the etag does not match. A reader cannot be served partially for range requests.
+    pub fn from_reader<S: Into<String>>(if_none_match: IfNoneMatch, reader: Box<Read>, etag: S, content_type: Option<String>, content_length: Option<u64>) -> EtaggedFileResponse {
+        let etag = etag.into();
+
+        let mut response = if if_none_match.matches(&etag) {
+            Self::new_response(None, etag, false, None, content_length, None)
+        } else {
+            Self::new_response(Some(EtaggedFileData::Reader(reader)), etag, false, content_type, content_length, None)
+        };
+
+        response.etag_source = EtagSource::Provided;
+
+        response
+    }
+
+    /// Create a EtaggedFileResponse instance with default options. A response without data is a `304 Not Modified` one.
+    fn new_response(data: Option<EtaggedFileData>, etag: String, weak: bool, content_type: Option<String>, content_length: Option<u64>, last_modified: Option<SystemTime>) -> EtaggedFileResponse {
+        EtaggedFileResponse {
+            is_etag_match: data.is_none(),
+            data,
+            etag,
+            weak,
+            content_type,
+            content_length,
+            last_modified,
+            content_disposition: None,
+            cache_control: None,
+            content_encoding: None,
+            vary: None,
+            nosniff: false,
+            digest: None,
+            expires: None,
+            cors_origin: None,
+            compression: None,
+            chunk_size: FILE_RESPONSE_CHUNK_SIZE,
+            etag_source: EtagSource::Computed,
+            age: None,
+            rate_limit: None,
+            cross_origin_resource_policy: None,
+            cross_origin_embedder_policy: None,
+            content_language: None,
+        }
+    }
+
+    /// Get the etag of this response without the `W/` prefix or quotes.
+    pub fn etag(&self) -> &str {
+        &self.etag
+    }
+
+    /// Get the length of the whole entity, if it is known. The `Content-Length` header of a ranged or compressed response is derived from the body actually sent instead.
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// Compute the etag which `from_bytes` and `from_embedded` emit for the given data, which is also the one `from` emits for a file with the same content.
+    pub fn etag_for_bytes(data: &[u8]) -> String {
+        Self::hash_bytes(data)
+    }
+
+    /// Compute the etag which `from` emits for a file with the default options, without an `EtagMap`.
+    pub fn etag_for_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
+        Self::hash_file::<Crc64Hasher>(path.as_ref(), FILE_RESPONSE_CHUNK_SIZE, false).map(|(etag, _)| etag)
+    }
+
+    /// Compute the etag of a file the same way `from` does, reusing and updating the `EtagMap`, without creating a response.
+    pub fn compute_etag<P: AsRef<Path>>(etag_map: &EtagMap, path: P) -> io::Result<String> {
+        let path = path.as_ref().canonicalize()?;
+
+        let metadata = fs::metadata(&path)?;
+
+        if !metadata.is_file() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "the path is not a file"));
+        }
+
+        Self::etag_of_file::<Crc64Hasher>(Some(etag_map), &path, false, FileInfo::new(&metadata, false), FILE_RESPONSE_CHUNK_SIZE, &|| Some(content_type::guess(&path, &[], &HashMap::new()))).map(|(cache, _, _)| cache.etag.clone())
+    }
+
+    /// Set the `Content-Disposition` header.
+    pub fn content_disposition<S: Into<String>>(mut self, content_disposition: S) -> Self {
+        self.content_disposition = Some(content_disposition.into());
+
+        self
+    }
+
+    /// Let the client download the file as an attachment with the given filename, which is independent of the path on disk, such as the original name of a file stored under an opaque key. A non-ASCII filename is sent in the RFC 5987 form with an ASCII fallback.
+    pub fn attachment<S: AsRef<str>>(self, filename: S) -> Self {
+        let content_disposition = disposition::attachment(filename.as_ref());
+
+        self.content_disposition(content_disposition)
+    }
+
+    /// Set the `Content-Type` header, replacing the one guessed from the extension of the file.
+    pub fn content_type<S: Into<String>>(mut self, content_type: S) -> Self {
+        self.content_type = Some(content_type.into());
+
+        self
+    }
+
+    /// Set the chunk size of the streamed body. A chunk size of 0, which would end the body at once, is treated as 1.
+    pub fn chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size.max(1);
+
+        self
+    }
+
+    /// Set the `Cache-Control` header, such as `max-age=31536000, immutable` for fingerprinted assets.
+    pub fn cache_control<S: Into<String>>(mut self, cache_control: S) -> Self {
+        self.cache_control = Some(cache_control.into());
+
+        self
+    }
+
+    /// Set the `Vary` header.
+    pub fn vary<S: Into<String>>(mut self, vary: S) -> Self {
+        self.vary = Some(vary.into());
+
+        self
+    }
+
+    /// Add a header name to the `Vary` header unless it is already there.
+    pub fn add_vary(mut self, name: &str) -> Self {
+        self.vary = match self.vary {
+            Some(vary) => {
+                if vary.split(',').any(|v| v.trim() == "*" || v.trim().eq_ignore_ascii_case(name)) {
+                    Some(vary)
+                } else {
+                    Some(format!("{}, {}", vary, name))
+                }
+            }
+            None => Some(name.to_string())
+        };
+
+        self
+    }
+
+    /// Allow the given origin, or any origin with `*`, to fetch the file cross-origin, such as fonts or WebAssembly modules on a separate asset host. A specific origin is added to the `Vary` header.
+    pub fn cors_origin<S: Into<String>>(mut self, origin: S) -> Self {
+        let origin = origin.into();
+
+        if origin != "*" {
+            self = self.add_vary("Origin");
+        }
+
+        self.cors_origin = Some(origin);
+
+        self
+    }
+
+    /// Set whether to emit `X-Content-Type-Options: nosniff` to prevent clients from sniffing the content type.
+    pub fn nosniff(mut self, nosniff: bool) -> Self {
+        self.nosniff = nosniff;
+
+        self
+    }
+
+    /// Set the `Content-Language` header, such as `de` or `en-US`, which is sent along with the body.
+    pub fn content_language<S: Into<String>>(mut self, content_language: S) -> Self {
+        self.content_language = Some(content_language.into());
+
+        self
+    }
+
+    /// Set the `Cross-Origin-Resource-Policy` header, such as `same-origin` or `cross-origin`.
+    pub fn cross_origin_resource_policy<S: Into<String>>(mut self, policy: S) -> Self {
+        self.cross_origin_resource_policy = Some(policy.into());
+
+        self
+    }
+
+    /// Set the `Cross-Origin-Embedder-Policy` header, such as `require-corp`.
+    pub fn cross_origin_embedder_policy<S: Into<String>>(mut self, policy: S) -> Self {
+        self.cross_origin_embedder_policy = Some(policy.into());
+
+        self
+    }
+
+    /// Limit the throughput of the body to `rate_limit` bytes per second, whether it is whole, ranged or compressed.
+    pub fn rate_limit(mut self, rate_limit: u64) -> Self {
+        self.rate_limit = Some(rate_limit);
+
+        self
+    }
+
+    /// Set whether the etag is emitted as a weak one (`W/"..."`).
+    pub fn weak(mut self, weak: bool) -> Self {
+        self.weak = weak;
+
+        self
+    }
+
+    /// Emit an `Expires` header for HTTP/1.0 caches, which is the response time plus the `max-age` of the `Cache-Control` header. Nothing is emitted if there is no `max-age`, so it should be called after `cache_control` or `immutable`.
+    pub fn expires(mut self) -> Self {
+        self.expires = self.cache_control.as_ref().and_then(|cache_control| max_age(cache_control)).map(Duration::from_secs);
+
+        self
+    }
+
+    /// Let the file be cached for a year without revalidation with `Cache-Control: public, max-age=31536000, immutable`, for fingerprinted assets such as `app.a1b2c3.js`. A `Cache-Control` header which has already been set is kept.
+    pub fn immutable(mut self) -> Self {
+        if self.cache_control.is_none() {
+            self.cache_control = Some(IMMUTABLE_CACHE_CONTROL.to_string());
+        }
+
+        self
+    }
+
+    /// Get the cached etag of a canonicalized file if it is still fresh.
+    fn cached_etag<H: EtagHasher>(etag_map: &EtagMap, path: &Path, weak: bool, file_size: u64, last_modified: Option<SystemTime>) -> Option<Arc<EtagCache>> {
+        etag_map.get(path).and_then(|cache| {
+            // the cached etag is stale if the file has been changed or it was produced by the other mode
+            if cache.algorithm == H::ALGORITHM && cache.weak == weak && cache.size == file_size && cache.modified == last_modified {
+                Some(cache)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Get the etag of a canonicalized file from the `EtagMap`, or compute and cache it along with the content type returned by `guess_content_type`, which returns `None` if the content type need not be guessed. The file opened for hashing is returned so that it can be reused for the response body, along with whether the etag was cached.
+    fn etag_of_file<H: EtagHasher>(etag_map: Option<&EtagMap>, path: &Path, weak: bool, info: FileInfo, chunk_size: u64, guess_content_type: &Fn() -> Option<String>) -> io::Result<(Arc<EtagCache>, Option<File>, EtagSource)> {
+        let FileInfo { size: file_size, modified: last_modified, mmap } = info;
+
+        let new_cache = |etag: String| {
+            Arc::new(EtagCache {
                 etag,
-                content_type: None,
-                content_length: None,
+                algorithm: H::ALGORITHM,
+                weak,
+                size: file_size,
+                modified: last_modified,
+                content_type: guess_content_type(),
             })
-        } else {
-            let file_size = match fs::metadata(&path) {
-                Ok(metadata) => {
-                    Some(metadata.len())
+        };
+
+        let etag_map = match etag_map {
+            Some(etag_map) => etag_map,
+            None => {
+                return if weak {
+                    Ok((new_cache(Self::compute_weak_etag(file_size, last_modified)), None, EtagSource::Computed))
+                } else {
+                    Self::hash_file::<H>(path, chunk_size, mmap).map(|(etag, file)| (new_cache(etag), Some(file), EtagSource::Computed))
+                };
+            }
+        };
+
+        let cached = || Self::cached_etag::<H>(etag_map, path, weak, file_size, last_modified);
+
+        let cache = |etag: String| {
+            let cache = new_cache(etag);
+
+            etag_map.insert_shared(path.to_path_buf(), cache.clone());
+
+            cache
+        };
+
+        match cached() {
+            Some(cache) => {
+                etag_map.record_hit();
+
+                Ok((cache, None, EtagSource::CacheHit))
+            }
+            None => {
+                if weak {
+                    etag_map.record_miss(Duration::from_secs(0));
+
+                    Ok((cache(Self::compute_weak_etag(file_size, last_modified)), None, EtagSource::Computed))
+                } else {
+                    let computing = etag_map.computing(path);
+
+                    let result = {
+                        let _computing = computing.lock().unwrap();
+
+                        // another request may have computed the etag while waiting for the lock
+                        match cached() {
+                            Some(cache) => {
+                                etag_map.record_hit();
+
+                                Ok((cache, None, EtagSource::CacheHit))
+                            }
+                            None => {
+                                let start = Instant::now();
+
+                                let result = Self::hash_file::<H>(path, chunk_size, mmap);
+
+                                etag_map.record_miss(start.elapsed());
+
+                                result.map(|(etag, file)| (cache(etag), Some(file), EtagSource::Computed))
+                            }
+                        }
+                    };
+
+                    etag_map.computed(path);
+
+                    result
                 }
-                Err(e) => return Err(e)
-            };
+            }
+        }
+    }
+
+    /// Hash a file on the background thread of the `EtagMap` and put its etag into `pending`. If the thread cannot be started, the path is left for a later request to hash.
+    fn hash_in_background<H: EtagHasher>(path: PathBuf, cache: EtagCache, chunk_size: u64, mmap: bool, pending: Arc<PendingEtags>) {
+        let job: HashJob<H> = HashJob::new(path.clone(), cache, chunk_size, mmap, pending.clone());
+
+        if !pending.run_in_background(Box::new(job)) {
+            pending.finish_hashing(&path);
+        }
+    }
 
-            let content_type = match path.extension() {
-                Some(extension) => {
-                    get_mime_type_str(&extension.to_str().unwrap().to_lowercase()).map(|t| { String::from(t) })
+    fn hash_file<H: EtagHasher>(path: &Path, chunk_size: u64, mmap: bool) -> io::Result<(String, File)> {
+        let mut hasher = H::default();
+
+        let chunk_size = chunk_size.max(1) as usize;
+
+        let read = File::open(path)?;
+
+        if mmap && mmap::hash(&read, &mut hasher, chunk_size) {
+            return Ok((hasher.finalize(), read));
+        }
+
+        let mut file = read;
+
+        HASH_BUFFER.with(|buffer| {
+            let mut buffer = buffer.borrow_mut();
+
+            if buffer.len() < chunk_size {
+                buffer.resize(chunk_size, 0);
+            }
+
+            let result = Self::hash_reader(&mut file, &mut hasher, &mut buffer[..chunk_size]);
+
+            // a worker thread keeps a buffer of a usual size for the next cache miss, but not one grown by a huge chunk size
+            if buffer.len() > MAX_RETAINED_HASH_BUFFER_SIZE {
+                *buffer = Vec::new();
+            }
+
+            result
+        })?;
+
+        let etag = hasher.finalize();
+
+        file.seek(SeekFrom::Start(0))?;
+
+        Ok((etag, file))
+    }
+
+    fn hash_reader<H: EtagHasher>(reader: &mut Read, hasher: &mut H, buffer: &mut [u8]) -> io::Result<()> {
+        loop {
+            match reader.read(buffer) {
+                Ok(c) => {
+                    if c == 0 {
+                        break;
+                    }
+                    hasher.update(&buffer[0..c]);
+                }
+                Err(error) => {
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compute_weak_etag(file_size: u64, last_modified: Option<SystemTime>) -> String {
+        let modified = match last_modified {
+            Some(last_modified) => last_modified.duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0)),
+            None => Duration::from_secs(0)
+        };
+
+        format!("{:X}-{:X}.{:X}", file_size, modified.as_secs(), modified.subsec_nanos())
+    }
+
+    /// Precompute the etags of all regular files under a directory recursively, so that the first requests for them need not hash. Symlinks to directories are not followed. Returns the number of cached files and the errors of files which could not be hashed.
+    pub fn warm<P: AsRef<Path>>(etag_map: &EtagMap, root: P) -> io::Result<(usize, Vec<(PathBuf, io::Error)>)> {
+        let mut count = 0;
+        let mut errors = Vec::new();
+
+        let mut directories = vec![root.as_ref().canonicalize()?];
+
+        while let Some(directory) = directories.pop() {
+            let entries = match fs::read_dir(&directory) {
+                Ok(entries) => entries,
+                Err(error) => {
+                    errors.push((directory, error));
+                    continue;
                 }
-                None => None
             };
 
-            let data = Box::from(File::open(&path)?);
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(error) => {
+                        errors.push((directory.clone(), error));
+                        continue;
+                    }
+                };
 
-            Ok(EtaggedFileResponse {
-                data: Some(data),
-                is_etag_match: false,
-                etag,
-                content_type,
-                content_length: file_size,
-            })
+                let path = entry.path();
+
+                match entry.file_type() {
+                    Ok(ref file_type) if file_type.is_dir() => directories.push(path),
+                    Ok(_) => {
+                        let result = path.canonicalize().and_then(|path| {
+                            let metadata = fs::metadata(&path)?;
+
+                            if metadata.is_file() {
+                                Self::etag_of_file::<Crc64Hasher>(Some(etag_map), &path, false, FileInfo::new(&metadata, false), FILE_RESPONSE_CHUNK_SIZE, &|| Some(content_type::guess(&path, &[], &HashMap::new()))).map(|_| true)
+                            } else {
+                                Ok(false)
+                            }
+                        });
+
+                        match result {
+                            Ok(true) => count += 1,
+                            Ok(false) => (),
+                            Err(error) => errors.push((path, error))
+                        }
+                    }
+                    Err(error) => errors.push((path, error))
+                }
+            }
         }
+
+        Ok((count, errors))
     }
 
     /// Create a new EtagMap instance.
     pub fn new_etag_map() -> EtagMap {
-        Mutex::from(HashMap::<String, String>::new())
+        EtagMap::new()
+    }
+
+    /// Create a new EtagMap instance which holds at most `capacity` entries and evicts the least recently used ones.
+    pub fn new_etag_map_with_capacity(capacity: usize) -> EtagMap {
+        EtagMap::with_capacity(capacity)
+    }
+
+    /// Create a new EtagMap instance whose cached etags expire after `ttl` and get recomputed.
+    pub fn new_etag_map_with_ttl(ttl: Duration) -> EtagMap {
+        EtagMap::with_ttl(ttl)
     }
-}
\ No newline at end of file
+
+    /// Load an EtagMap instance saved by `EtagMap::save`, so that a restart reuses the etags of unchanged files.
+    pub fn load_etag_map<P: AsRef<Path>>(path: P) -> io::Result<EtagMap> {
+        EtagMap::load(path)
+    }
+}
@@ -0,0 +1,44 @@
+use std::fs::File;
+
+#[cfg(feature = "mmap")]
+use std::io::Cursor;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use {EtagHasher, ReadSeek};
+
+/// Feed a memory-mapped file into the hasher. Returns `false` if the file cannot be mapped or the `mmap` feature is disabled, so that the file should be read instead.
+#[cfg(feature = "mmap")]
+pub(crate) fn hash<H: EtagHasher>(file: &File, hasher: &mut H, chunk_size: usize) -> bool {
+    // the map is only valid as long as the file is not truncated by another process
+    match unsafe { Mmap::map(file) } {
+        Ok(mapped) => {
+            for chunk in mapped.chunks(chunk_size) {
+                hasher.update(chunk);
+            }
+
+            true
+        }
+        Err(_) => false
+    }
+}
+
+#[cfg(not(feature = "mmap"))]
+pub(crate) fn hash<H: EtagHasher>(_file: &File, _hasher: &mut H, _chunk_size: usize) -> bool {
+    false
+}
+
+/// Map a file into memory as the response body. The map is owned by the body, so it lives until the body has been sent. Returns `None` if the file cannot be mapped or the `mmap` feature is disabled.
+#[cfg(feature = "mmap")]
+pub(crate) fn body(file: &File) -> Option<Box<ReadSeek>> {
+    match unsafe { Mmap::map(file) } {
+        Ok(mapped) => Some(Box::new(Cursor::new(mapped))),
+        Err(_) => None
+    }
+}
+
+#[cfg(not(feature = "mmap"))]
+pub(crate) fn body(_file: &File) -> Option<Box<ReadSeek>> {
+    None
+}
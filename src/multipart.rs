@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom, Cursor, ErrorKind};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ReadSeek;
+
+enum Segment {
+    /// The boundary and the headers of a part.
+    Bytes(Cursor<Vec<u8>>),
+    /// The start position and the remaining length of a range of the file.
+    Range(u64, u64),
+}
+
+/// The body of a `multipart/byteranges` response, which reads the ranges of the file between the boundaries and the headers of the parts.
+pub(crate) struct MultipartByteranges {
+    data: Box<ReadSeek>,
+    segments: VecDeque<Segment>,
+    is_positioned: bool,
+}
+
+impl MultipartByteranges {
+    /// Create the body for the ranges, which should not overlap. Returns the body, its exact length and its boundary.
+    pub(crate) fn new(data: Box<ReadSeek>, ranges: &[(u64, u64)], total: u64, content_type: Option<&str>) -> (MultipartByteranges, u64, String) {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() ^ u64::from(d.subsec_nanos()) << 20).unwrap_or(0);
+
+        let boundary = format!("BYTERANGES{:016X}", nanos);
+
+        let mut segments = VecDeque::with_capacity(ranges.len() * 2 + 1);
+
+        let mut length = 0;
+
+        for &(start, end) in ranges {
+            let mut headers = format!("\r\n--{}\r\n", boundary);
+
+            if let Some(content_type) = content_type {
+                headers.push_str(&format!("Content-Type: {}\r\n", content_type));
+            }
+
+            headers.push_str(&format!("Content-Range: bytes {}-{}/{}\r\n\r\n", start, end, total));
+
+            let range_length = end - start + 1;
+
+            length += headers.len() as u64 + range_length;
+
+            segments.push_back(Segment::Bytes(Cursor::new(headers.into_bytes())));
+            segments.push_back(Segment::Range(start, range_length));
+        }
+
+        let closing = format!("\r\n--{}--\r\n", boundary);
+
+        length += closing.len() as u64;
+
+        segments.push_back(Segment::Bytes(Cursor::new(closing.into_bytes())));
+
+        (MultipartByteranges {
+            data,
+            segments,
+            is_positioned: false,
+        }, length, boundary)
+    }
+}
+
+impl Read for MultipartByteranges {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let c = match self.segments.front_mut() {
+                Some(&mut Segment::Bytes(ref mut bytes)) => bytes.read(buf)?,
+                Some(&mut Segment::Range(ref mut start, ref mut remaining)) => {
+                    if *remaining == 0 {
+                        0
+                    } else {
+                        if !self.is_positioned {
+                            self.data.seek(SeekFrom::Start(*start))?;
+
+                            self.is_positioned = true;
+                        }
+
+                        let max = (buf.len() as u64).min(*remaining) as usize;
+
+                        let c = self.data.read(&mut buf[..max])?;
+
+                        // the length of the body has been sent, so a truncated file cannot be padded
+                        if c == 0 {
+                            return Err(io::Error::new(ErrorKind::UnexpectedEof, "the file has been truncated"));
+                        }
+
+                        *start += c as u64;
+                        *remaining -= c as u64;
+
+                        c
+                    }
+                }
+                None => return Ok(0)
+            };
+
+            if c == 0 {
+                self.segments.pop_front();
+
+                self.is_positioned = false;
+            } else {
+                return Ok(c);
+            }
+        }
+    }
+}
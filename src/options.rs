@@ -0,0 +1,24 @@
+use rocket::response::{self, Response, Responder};
+use rocket::http::Status;
+use rocket::request::Request;
+
+/// The responder for `OPTIONS` requests to the routes serving files. It is `204 No Content` with `Allow: GET, HEAD, OPTIONS` and `Accept-Ranges: bytes`.
+///
+/// ```ignore
+/// #[options("/static/<path..>")]
+/// fn static_options(path: PathBuf) -> EtaggedFileOptions {
+///     EtaggedFileOptions
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EtaggedFileOptions;
+
+impl<'a> Responder<'a> for EtaggedFileOptions {
+    fn respond_to(self, _: &Request) -> response::Result<'a> {
+        Response::build()
+            .status(Status::NoContent)
+            .raw_header("Allow", "GET, HEAD, OPTIONS")
+            .raw_header("Accept-Ranges", "bytes")
+            .ok()
+    }
+}
@@ -0,0 +1,217 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rocket::http::hyper::header::HttpDate;
+
+/// The maximum number of ranges in a `Range` header. A header with more ranges is ignored, so that a request cannot make the response much larger than the resource.
+const MAX_RANGES: usize = 32;
+
+/// The result of parsing a `Range` request header against a resource of a known length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ByteRange {
+    /// A single satisfiable range. Both positions are inclusive.
+    Satisfiable(u64, u64),
+    /// Multiple satisfiable ranges which do not overlap, in ascending order, to be sent as `multipart/byteranges`.
+    Multiple(Vec<(u64, u64)>),
+    /// The range cannot be satisfied by the resource.
+    Unsatisfiable,
+    /// The header should be ignored and the whole resource should be served.
+    Ignored,
+}
+
+/// The result of parsing one range of a `Range` header.
+enum Spec {
+    Satisfiable(u64, u64),
+    Unsatisfiable,
+    Malformed,
+}
+
+impl ByteRange {
+    /// Parse the value of a `Range` header. Multiple ranges are sorted and coalesced if they overlap or are adjacent, and unsatisfiable ones among them are dropped.
+    ///
+    /// A header which is syntactically malformed, such as `bytes=5-1` or `bytes=a-`, or which uses another unit, is ignored so that the whole resource is served with `200 OK`. A well-formed header none of whose ranges overlaps the resource, such as `bytes=99999999-` for a small file, is unsatisfiable and gets `416 Range Not Satisfiable`.
+    pub(crate) fn parse(value: &str, total: u64) -> ByteRange {
+        let value = value.trim();
+
+        if !value.starts_with("bytes=") {
+            return ByteRange::Ignored;
+        }
+
+        let specs: Vec<&str> = value[6..].split(',').map(|spec| spec.trim()).filter(|spec| !spec.is_empty()).collect();
+
+        if specs.is_empty() || specs.len() > MAX_RANGES {
+            return ByteRange::Ignored;
+        }
+
+        let mut ranges = Vec::with_capacity(specs.len());
+
+        for spec in specs {
+            match Self::parse_spec(spec, total) {
+                Spec::Satisfiable(start, end) => ranges.push((start, end)),
+                Spec::Unsatisfiable => (),
+                Spec::Malformed => return ByteRange::Ignored
+            }
+        }
+
+        ranges.sort();
+
+        let mut coalesced: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+
+        for (start, end) in ranges {
+            if let Some(last) = coalesced.last_mut() {
+                if start <= last.1 + 1 {
+                    last.1 = last.1.max(end);
+                    continue;
+                }
+            }
+
+            coalesced.push((start, end));
+        }
+
+        match coalesced.len() {
+            0 => ByteRange::Unsatisfiable,
+            1 => ByteRange::Satisfiable(coalesced[0].0, coalesced[0].1),
+            _ => ByteRange::Multiple(coalesced)
+        }
+    }
+
+    fn parse_spec(spec: &str, total: u64) -> Spec {
+        let dash = match spec.find('-') {
+            Some(dash) => dash,
+            None => return Spec::Malformed
+        };
+
+        let start = spec[..dash].trim();
+        let end = spec[(dash + 1)..].trim();
+
+        if start.is_empty() {
+            // suffix range, e.g. `bytes=-500`
+            let suffix_length: u64 = match end.parse() {
+                Ok(suffix_length) => suffix_length,
+                Err(_) => return Spec::Malformed
+            };
+
+            if suffix_length == 0 || total == 0 {
+                return Spec::Unsatisfiable;
+            }
+
+            let suffix_length = suffix_length.min(total);
+
+            return Spec::Satisfiable(total - suffix_length, total - 1);
+        }
+
+        let start: u64 = match start.parse() {
+            Ok(start) => start,
+            Err(_) => return Spec::Malformed
+        };
+
+        let end: Option<u64> = if end.is_empty() {
+            None
+        } else {
+            match end.parse() {
+                Ok(end) => Some(end),
+                Err(_) => return Spec::Malformed
+            }
+        };
+
+        if let Some(end) = end {
+            if end < start {
+                return Spec::Malformed;
+            }
+        }
+
+        if start >= total {
+            return Spec::Unsatisfiable;
+        }
+
+        let end = match end {
+            Some(end) => end.min(total - 1),
+            None => total - 1
+        };
+
+        Spec::Satisfiable(start, end)
+    }
+}
+
+/// Whether the value of an `If-Range` header still identifies the current resource, so that a `Range` header can be honored. An entity tag must match strongly, so a weak one never matches, and a date must equal the modification time at the precision of seconds.
+///
+/// A modification time is only a strong validator if it is at least one second in the past, because a file can be rewritten again within the same second without changing its `Last-Modified`. Otherwise a resumed download could get a range of the new content appended to the old content.
+pub(crate) fn if_range_matches(value: &str, etag: &str, weak: bool, last_modified: Option<SystemTime>) -> bool {
+    let value = value.trim();
+
+    if value.starts_with("W/") {
+        return false;
+    }
+
+    if value.starts_with('"') {
+        return !weak && value.len() >= 2 && value.ends_with('"') && &value[1..(value.len() - 1)] == etag;
+    }
+
+    match (value.parse::<HttpDate>(), last_modified) {
+        (Ok(date), Some(last_modified)) => {
+            let to_secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).ok();
+
+            let date = to_secs(SystemTime::from(date));
+
+            let is_strong = SystemTime::now().duration_since(last_modified).map(|elapsed| elapsed.as_secs() >= 1).unwrap_or(false);
+
+            is_strong && date.is_some() && date == to_secs(last_modified)
+        }
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn malformed_ranges_are_ignored() {
+        assert_eq!(ByteRange::Ignored, ByteRange::parse("bytes=5-1", 100));
+        assert_eq!(ByteRange::Ignored, ByteRange::parse("bytes=a-", 100));
+        assert_eq!(ByteRange::Ignored, ByteRange::parse("items=0-1", 100));
+    }
+
+    #[test]
+    fn out_of_bounds_ranges_are_unsatisfiable() {
+        assert_eq!(ByteRange::Unsatisfiable, ByteRange::parse("bytes=99999999-", 100));
+        assert_eq!(ByteRange::Unsatisfiable, ByteRange::parse("bytes=100-200, 300-", 100));
+    }
+
+    #[test]
+    fn ranges_are_clamped_to_the_resource() {
+        assert_eq!(ByteRange::Satisfiable(10, 99), ByteRange::parse("bytes=10-999", 100));
+        assert_eq!(ByteRange::Satisfiable(90, 99), ByteRange::parse("bytes=-10", 100));
+    }
+
+    #[test]
+    fn multiple_ranges_are_sorted_and_coalesced() {
+        assert_eq!(ByteRange::Multiple(vec![(0, 19), (50, 59)]), ByteRange::parse("bytes=50-59, 10-19, 0-9", 100));
+        assert_eq!(ByteRange::Satisfiable(0, 29), ByteRange::parse("bytes=0-9, 5-19, 20-29", 100));
+        assert_eq!(ByteRange::Satisfiable(0, 9), ByteRange::parse("bytes=0-9, 200-", 100));
+    }
+
+    #[test]
+    fn if_range_matches_a_strong_etag_only() {
+        assert!(if_range_matches("\"ABC\"", "ABC", false, None));
+        assert!(!if_range_matches("\"ABC\"", "ABC", true, None));
+        assert!(!if_range_matches("W/\"ABC\"", "ABC", false, None));
+        assert!(!if_range_matches("\"ABD\"", "ABC", false, None));
+    }
+
+    #[test]
+    fn if_range_matches_the_date_of_an_old_enough_file() {
+        let last_modified = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+
+        let date = HttpDate::from(last_modified).to_string();
+
+        assert!(if_range_matches(&date, "ABC", false, Some(last_modified)));
+        assert!(!if_range_matches(&date, "ABC", false, Some(last_modified + Duration::from_secs(1))));
+
+        // a file modified within the last second may be modified again without changing its date
+        let now = SystemTime::now();
+
+        assert!(!if_range_matches(&HttpDate::from(now).to_string(), "ABC", false, Some(now)));
+    }
+}
@@ -0,0 +1,47 @@
+use std::io::{self, Read};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A reader which sleeps as needed to keep its average throughput at most `rate` bytes per second since the first read.
+pub(crate) struct RateLimited<R: Read> {
+    reader: R,
+    rate: u64,
+    read: u64,
+    start: Option<Instant>,
+}
+
+impl<R: Read> RateLimited<R> {
+    /// The `rate` must be greater than 0.
+    pub(crate) fn new(reader: R, rate: u64) -> RateLimited<R> {
+        RateLimited {
+            reader,
+            rate,
+            read: 0,
+            start: None,
+        }
+    }
+}
+
+impl<R: Read> Read for RateLimited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+
+        // the time at which the bytes read so far are allowed by the rate
+        let due = Duration::from_secs(self.read / self.rate) + Duration::from_nanos(((self.read % self.rate) as f64 / self.rate as f64 * 1e9) as u64);
+
+        let elapsed = start.elapsed();
+
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        }
+
+        // reading at most a second's worth at once keeps the bursts small
+        let max = (buf.len() as u64).min(self.rate) as usize;
+
+        let c = self.reader.read(&mut buf[..max])?;
+
+        self.read += c as u64;
+
+        Ok(c)
+    }
+}
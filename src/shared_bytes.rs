@@ -0,0 +1,11 @@
+use std::sync::Arc;
+
+/// Bytes shared between responses, such as a file body cached in an `EtagMap` or an asset loaded at startup, which can be read through a `Cursor`.
+#[derive(Debug, Clone)]
+pub(crate) struct SharedBytes<T: AsRef<[u8]> + ?Sized>(pub(crate) Arc<T>);
+
+impl<T: AsRef<[u8]> + ?Sized> AsRef<[u8]> for SharedBytes<T> {
+    fn as_ref(&self) -> &[u8] {
+        (*self.0).as_ref()
+    }
+}
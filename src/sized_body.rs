@@ -0,0 +1,36 @@
+use std::io::{self, ErrorKind, Read};
+
+/// A reader which produces exactly the number of bytes announced by the `Content-Length` header. Extra bytes, such as those appended to a file after it was stat'ed, are cut off, and a body which ends too early is an error, so that the connection is closed rather than leaving the client waiting for the missing bytes.
+pub(crate) struct SizedBody<R: Read> {
+    reader: R,
+    remaining: u64,
+}
+
+impl<R: Read> SizedBody<R> {
+    pub(crate) fn new(reader: R, content_length: u64) -> SizedBody<R> {
+        SizedBody {
+            reader,
+            remaining: content_length,
+        }
+    }
+}
+
+impl<R: Read> Read for SizedBody<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let max = (buf.len() as u64).min(self.remaining) as usize;
+
+        let c = self.reader.read(&mut buf[..max])?;
+
+        if c == 0 {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, format!("the body ended {} bytes before its Content-Length", self.remaining)));
+        }
+
+        self.remaining -= c as u64;
+
+        Ok(c)
+    }
+}
@@ -0,0 +1,109 @@
+#![feature(plugin)]
+#![plugin(rocket_codegen)]
+
+extern crate rocket;
+extern crate rocket_etagged_file_response;
+
+use std::io::Cursor;
+
+use rocket::local::Client;
+use rocket::http::{Header, Status};
+
+use rocket_etagged_file_response::{EtaggedFileResponse, IfNoneMatch};
+
+const DATA: &[u8] = b"0123456789abcdef";
+
+#[get("/data")]
+fn data(if_none_match: IfNoneMatch) -> EtaggedFileResponse {
+    EtaggedFileResponse::from_bytes(if_none_match, DATA.to_vec(), Some("text/plain".to_string()))
+}
+
+#[head("/head")]
+fn head(if_none_match: IfNoneMatch) -> EtaggedFileResponse {
+    EtaggedFileResponse::from_bytes(if_none_match, DATA.to_vec(), Some("text/plain".to_string()))
+}
+
+#[get("/reader")]
+fn reader(if_none_match: IfNoneMatch) -> EtaggedFileResponse {
+    EtaggedFileResponse::from_reader(if_none_match, Box::new(Cursor::new(DATA)), "READER", Some("text/plain".to_string()), None).chunk_size(0)
+}
+
+fn client() -> Client {
+    Client::new(rocket::ignite().mount("/", routes![data, head, reader])).unwrap()
+}
+
+#[test]
+fn a_range_is_sliced_from_the_bytes() {
+    let client = client();
+
+    let mut response = client.get("/data").header(Header::new("Range", "bytes=2-5")).dispatch();
+
+    assert_eq!(Status::PartialContent, response.status());
+    assert_eq!(Some("bytes 2-5/16"), response.headers().get_one("Content-Range"));
+    assert_eq!(Some("4"), response.headers().get_one("Content-Length"));
+    assert_eq!(Some(b"2345".to_vec()), response.body_bytes());
+}
+
+#[test]
+fn a_matching_etag_of_bytes_is_not_modified() {
+    let client = client();
+
+    let etag = format!("\"{}\"", EtaggedFileResponse::etag_for_bytes(DATA));
+
+    assert_eq!(Some(etag.as_str()), client.get("/data").dispatch().headers().get_one("ETag"));
+
+    let mut response = client.get("/data").header(Header::new("If-None-Match", etag)).dispatch();
+
+    assert_eq!(Status::NotModified, response.status());
+    assert!(response.body_bytes().unwrap_or_default().is_empty());
+}
+
+#[test]
+fn a_not_modified_response_keeps_the_etag() {
+    let client = client();
+
+    let etag = client.get("/data").dispatch().headers().get_one("ETag").unwrap().to_string();
+
+    let response = client.get("/data").header(Header::new("If-None-Match", etag.clone())).dispatch();
+
+    assert_eq!(Status::NotModified, response.status());
+    assert_eq!(Some(etag.as_str()), response.headers().get_one("ETag"));
+}
+
+#[test]
+fn a_not_modified_response_has_the_entity_length() {
+    let client = client();
+
+    let etag = client.get("/data").dispatch().headers().get_one("ETag").unwrap().to_string();
+
+    let response = client.get("/data").header(Header::new("If-None-Match", etag)).dispatch();
+
+    assert_eq!(Status::NotModified, response.status());
+    assert_eq!(Some("16"), response.headers().get_one("Content-Length"));
+}
+
+#[test]
+fn a_head_request_has_the_headers_but_no_body() {
+    let client = client();
+
+    // the GET route answers a HEAD request without a `#[head]` route
+    for uri in vec!["/data", "/head"] {
+        let mut response = client.head(uri).dispatch();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some("16"), response.headers().get_one("Content-Length"));
+        assert_eq!(Some("text/plain"), response.headers().get_one("Content-Type"));
+        assert!(response.headers().get_one("ETag").is_some());
+        assert!(response.body_bytes().unwrap_or_default().is_empty());
+    }
+}
+
+#[test]
+fn a_chunk_size_of_zero_still_streams_the_whole_body() {
+    let client = client();
+
+    let mut response = client.get("/reader").dispatch();
+
+    assert_eq!(Status::Ok, response.status());
+    assert_eq!(Some(DATA.to_vec()), response.body_bytes());
+}
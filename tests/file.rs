@@ -0,0 +1,301 @@
+#![feature(plugin)]
+#![plugin(rocket_codegen)]
+
+extern crate rocket;
+extern crate rocket_etagged_file_response;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{SystemTime, Duration};
+
+use rocket::State;
+use rocket::local::Client;
+use rocket::http::{Header, Status};
+
+use rocket_etagged_file_response::{EtaggedFileResponse, EtaggedFileResponseBuilder, EtaggedFileResult, EtaggedFileError, EtagMap, EtagSource, IfNoneMatch, IfModifiedSince, AcceptEncoding};
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("rocket-etagged-file-response-{}-{}", process::id(), name))
+}
+
+fn temp_file(name: &str, content: &[u8]) -> PathBuf {
+    let path = temp_path(name);
+
+    fs::write(&path, content).unwrap();
+
+    path
+}
+
+#[get("/single-pass/<name>")]
+fn single_pass(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, name: String) -> EtaggedFileResult {
+    EtaggedFileResponseBuilder::new(temp_path(&name)).etag_map(&etag_map).single_pass(true).build(if_none_match).into()
+}
+
+#[get("/file/<name>")]
+fn file(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, name: String) -> EtaggedFileResult {
+    EtaggedFileResponse::from_etag_map(&etag_map, if_none_match, no_if_modified_since(), temp_path(&name)).into()
+}
+
+#[get("/compressible/<name>")]
+fn compressible(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, accept_encoding: AcceptEncoding, name: String) -> EtaggedFileResult {
+    EtaggedFileResponseBuilder::new(temp_path(&name)).etag_map(&etag_map).accept_encoding(accept_encoding).build(if_none_match).into()
+}
+
+fn no_if_modified_since() -> IfModifiedSince {
+    IfModifiedSince {
+        date: None
+    }
+}
+
+fn client() -> Client {
+    Client::new(rocket::ignite().manage(EtagMap::new()).mount("/", routes![single_pass, file, compressible])).unwrap()
+}
+
+#[test]
+fn single_pass_caches_the_etag_for_the_next_request() {
+    temp_file("single-pass.txt", b"hashed while being sent");
+
+    let client = client();
+
+    let mut response = client.get("/single-pass/single-pass.txt").dispatch();
+
+    assert_eq!(Status::Ok, response.status());
+    assert_eq!(None, response.headers().get_one("ETag"));
+    assert_eq!(Some(b"hashed while being sent".to_vec()), response.body_bytes());
+
+    let response = client.get("/single-pass/single-pass.txt").dispatch();
+
+    assert_eq!(Status::Ok, response.status());
+    assert!(response.headers().get_one("ETag").is_some());
+}
+
+#[test]
+fn a_deferred_etag_does_not_fail_if_match() {
+    temp_file("single-pass-if-match.txt", b"hashed while checked");
+
+    let client = client();
+
+    let if_match = format!("\"{}\"", EtaggedFileResponse::etag_for_bytes(b"hashed while checked"));
+
+    let mut response = client.get("/single-pass/single-pass-if-match.txt").header(Header::new("If-Match", if_match)).dispatch();
+
+    assert_eq!(Status::Ok, response.status());
+    assert_eq!(Some(b"hashed while checked".to_vec()), response.body_bytes());
+}
+
+#[test]
+fn rewriting_a_file_produces_a_fresh_etag() {
+    let path = temp_file("rewrite.txt", b"the first version");
+
+    let etag_map = EtagMap::new();
+
+    let first = EtaggedFileResponse::from_etag_map(&etag_map, IfNoneMatch::default(), no_if_modified_since(), &path).unwrap();
+
+    assert_eq!(EtaggedFileResponse::etag_for_bytes(b"the first version"), first.etag());
+
+    // the size changes too, so the rewrite is noticed even within the precision of the modification time
+    fs::write(&path, b"the second, longer version").unwrap();
+
+    let second = EtaggedFileResponse::from_etag_map(&etag_map, IfNoneMatch::default(), no_if_modified_since(), &path).unwrap();
+
+    assert_ne!(first.etag(), second.etag());
+    assert_eq!(EtaggedFileResponse::etag_for_bytes(b"the second, longer version"), second.etag());
+}
+
+#[test]
+fn if_modified_since_is_ignored_along_with_if_none_match() {
+    let path = temp_file("if-modified-since.txt", b"rewritten without changing its date");
+
+    let etag_map = EtagMap::new();
+
+    let if_modified_since = IfModifiedSince {
+        date: Some(SystemTime::now() + Duration::from_secs(3600))
+    };
+
+    let response = EtaggedFileResponse::from_etag_map(&etag_map, IfNoneMatch::parse("\"STALE\""), if_modified_since, &path).unwrap();
+
+    assert!(!response.is_etag_match);
+    assert!(response.data.is_some());
+
+    let response = EtaggedFileResponse::from_etag_map(&etag_map, IfNoneMatch::default(), if_modified_since, &path).unwrap();
+
+    assert!(response.is_etag_match);
+}
+
+#[test]
+fn a_not_modified_file_keeps_its_length() {
+    let path = temp_file("not-modified.txt", b"twelve bytes");
+
+    let etag_map = EtagMap::new();
+
+    let if_none_match = IfNoneMatch::parse(&format!("\"{}\"", EtaggedFileResponse::etag_for_bytes(b"twelve bytes")));
+
+    let response = EtaggedFileResponse::from_etag_map(&etag_map, if_none_match, no_if_modified_since(), &path).unwrap();
+
+    assert!(response.is_etag_match);
+    assert_eq!(Some(12), response.content_length());
+}
+
+#[test]
+fn a_trusted_cache_hit_does_not_read_the_metadata_again() {
+    let path = temp_file("trusted.txt", b"the cached version");
+
+    let etag_map = EtagMap::new();
+
+    let first = EtaggedFileResponseBuilder::new(&path).etag_map(&etag_map).revalidate(false).build(IfNoneMatch::default()).unwrap();
+
+    assert_eq!(EtagSource::Computed, first.etag_source);
+
+    // a rewrite to another size is only noticed by reading the metadata
+    fs::write(&path, b"a rewritten version of another size").unwrap();
+
+    let second = EtaggedFileResponseBuilder::new(&path).etag_map(&etag_map).revalidate(false).build(IfNoneMatch::default()).unwrap();
+
+    assert_eq!(EtagSource::CacheHit, second.etag_source);
+    assert_eq!(first.etag(), second.etag());
+    assert_eq!(Some(18), second.content_length());
+}
+
+#[test]
+fn a_download_resumed_after_a_rewrite_gets_the_whole_new_file() {
+    temp_file("resumed.txt", b"the first version");
+
+    let client = client();
+
+    let response = client.get("/file/resumed.txt").dispatch();
+
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+    let last_modified = response.headers().get_one("Last-Modified").unwrap().to_string();
+
+    let response = client.get("/file/resumed.txt").header(Header::new("Range", "bytes=4-")).header(Header::new("If-Range", etag.clone())).dispatch();
+
+    assert_eq!(Status::PartialContent, response.status());
+
+    fs::write(temp_path("resumed.txt"), b"the second, longer version").unwrap();
+
+    // neither the old etag nor the old date may resume into the new content
+    for if_range in vec![etag, last_modified] {
+        let mut response = client.get("/file/resumed.txt").header(Header::new("Range", "bytes=4-")).header(Header::new("If-Range", if_range)).dispatch();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(None, response.headers().get_one("Content-Range"));
+        assert_eq!(Some(b"the second, longer version".to_vec()), response.body_bytes());
+    }
+}
+
+#[test]
+fn an_empty_file_is_sized_with_a_stable_etag() {
+    temp_file("empty.txt", b"");
+
+    let client = client();
+
+    let etag = format!("\"{}\"", EtaggedFileResponse::etag_for_bytes(b""));
+
+    for _ in 0..2 {
+        let mut response = client.get("/compressible/empty.txt").header(Header::new("Accept-Encoding", "gzip, br")).dispatch();
+
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some("0"), response.headers().get_one("Content-Length"));
+        assert_eq!(None, response.headers().get_one("Content-Encoding"));
+        assert_eq!(Some(etag.as_str()), response.headers().get_one("ETag"));
+        assert!(response.body_bytes().unwrap_or_default().is_empty());
+    }
+}
+
+#[test]
+fn only_a_textual_file_gets_a_charset() {
+    temp_file("charset.html", b"<p>text</p>");
+    temp_file("charset.png", b"\x89PNG");
+
+    let client = client();
+
+    assert_eq!(Some("text/html; charset=utf-8"), client.get("/file/charset.html").dispatch().headers().get_one("Content-Type"));
+    assert_eq!(Some("image/png"), client.get("/file/charset.png").dispatch().headers().get_one("Content-Type"));
+}
+
+#[test]
+fn concurrent_requests_for_a_cold_path_hash_once() {
+    let path = temp_file("concurrent.bin", &vec![b'x'; 8 * 1024 * 1024]);
+
+    let etag_map = Arc::new(EtagMap::new());
+
+    let barrier = Arc::new(Barrier::new(2));
+
+    let threads: Vec<_> = (0..2).map(|_| {
+        let path = path.clone();
+        let etag_map = etag_map.clone();
+        let barrier = barrier.clone();
+
+        thread::spawn(move || {
+            barrier.wait();
+
+            EtaggedFileResponse::from_etag_map(&etag_map, IfNoneMatch::default(), no_if_modified_since(), &path).unwrap().etag().to_string()
+        })
+    }).collect();
+
+    let etags: Vec<String> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+
+    assert_eq!(etags[0], etags[1]);
+
+    let stats = etag_map.stats();
+
+    assert_eq!(1, stats.misses);
+    assert_eq!(1, stats.hits);
+}
+
+#[cfg(unix)]
+#[test]
+fn a_non_utf8_filename_is_served() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut name = format!("rocket-etagged-file-response-{}-", process::id()).into_bytes();
+
+    name.extend_from_slice(b"caf\xe9.txt");
+
+    let path = env::temp_dir().join(OsStr::from_bytes(&name));
+
+    fs::write(&path, b"not valid UTF-8").unwrap();
+
+    let etag_map = EtagMap::new();
+
+    let response = EtaggedFileResponse::from_etag_map(&etag_map, IfNoneMatch::default(), no_if_modified_since(), &path).unwrap();
+
+    assert_eq!(EtaggedFileResponse::etag_for_bytes(b"not valid UTF-8"), response.etag());
+    assert_eq!(Some(15), response.content_length());
+}
+
+#[cfg(unix)]
+#[test]
+fn an_unreadable_file_is_forbidden() {
+    use std::fs::{File, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    // an unreadable file left by an earlier run cannot be written again
+    let _ = fs::remove_file(temp_path("unreadable.txt"));
+
+    let path = temp_file("unreadable.txt", b"secret");
+
+    fs::set_permissions(&path, Permissions::from_mode(0o000)).unwrap();
+
+    // permissions do not apply to root, so there is nothing to test
+    if File::open(&path).is_ok() {
+        return;
+    }
+
+    let etag_map = EtagMap::new();
+
+    match EtaggedFileResponse::from_etag_map(&etag_map, IfNoneMatch::default(), no_if_modified_since(), &path) {
+        Err(EtaggedFileError::PermissionDenied) => (),
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("an unreadable file is served")
+    }
+
+    let client = client();
+
+    assert_eq!(Status::Forbidden, client.get("/file/unreadable.txt").dispatch().status());
+}
@@ -0,0 +1,159 @@
+#![feature(plugin)]
+#![plugin(rocket_codegen)]
+
+extern crate rocket;
+extern crate rocket_etagged_file_response;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use rocket::State;
+use rocket::local::Client;
+use rocket::http::Status;
+
+use rocket_etagged_file_response::{EtaggedFileResponse, EtaggedFileResult, EtagMap, IfNoneMatch, IfModifiedSince};
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("rocket-etagged-file-response-{}-{}", process::id(), name))
+}
+
+/// Create a root directory with `inside.txt` and `sub/index.html`, next to an `outside.txt` which must never be served through it.
+fn root_dir(name: &str) -> PathBuf {
+    let root = temp_path(name);
+
+    fs::create_dir_all(root.join("sub")).unwrap();
+
+    fs::write(root.join("inside.txt"), b"inside").unwrap();
+    fs::write(root.join("sub").join("index.html"), b"<p>index</p>").unwrap();
+
+    fs::write(temp_path("outside.txt"), b"outside").unwrap();
+
+    root
+}
+
+/// Percent-encode the slashes of a path, so that it is passed to a route as a single segment.
+fn segment(path: &str) -> String {
+    path.replace('/', "%2F")
+}
+
+fn no_if_modified_since() -> IfModifiedSince {
+    IfModifiedSince {
+        date: None
+    }
+}
+
+#[get("/rooted/<root>/<relative>")]
+fn rooted(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, root: String, relative: String) -> EtaggedFileResult {
+    EtaggedFileResponse::from_rooted(etag_map, if_none_match, no_if_modified_since(), temp_path(&root), relative).into()
+}
+
+#[get("/dir/<root>/<relative>")]
+fn dir(etag_map: State<EtagMap>, if_none_match: IfNoneMatch, root: String, relative: String) -> EtaggedFileResult {
+    EtaggedFileResponse::from_dir(etag_map, if_none_match, no_if_modified_since(), temp_path(&root), relative, &["index.html"]).into()
+}
+
+fn client() -> Client {
+    Client::new(rocket::ignite().manage(EtagMap::new()).mount("/", routes![rooted, dir])).unwrap()
+}
+
+fn status(client: &Client, uri: String) -> Status {
+    client.get(uri).dispatch().status()
+}
+
+#[test]
+fn a_file_under_the_root_is_served() {
+    root_dir("rooted-inside");
+
+    let client = client();
+
+    assert_eq!(Status::Ok, status(&client, "/rooted/rooted-inside/inside.txt".to_string()));
+    assert_eq!(Status::Ok, status(&client, format!("/rooted/rooted-inside/{}", segment("sub/../inside.txt"))));
+}
+
+#[test]
+fn a_relative_escape_from_the_root_is_not_found() {
+    root_dir("rooted-escape");
+
+    let client = client();
+
+    let outside = format!("../rocket-etagged-file-response-{}-outside.txt", process::id());
+
+    assert_eq!(Status::NotFound, status(&client, format!("/rooted/rooted-escape/{}", segment(&outside))));
+    assert_eq!(Status::NotFound, status(&client, format!("/rooted/rooted-escape/{}", segment(&format!("sub/../{}", outside)))));
+
+    if cfg!(unix) {
+        assert_eq!(Status::NotFound, status(&client, format!("/rooted/rooted-escape/{}", segment("../../../../../../../../etc/passwd"))));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn an_absolute_relative_path_is_not_found() {
+    root_dir("rooted-absolute");
+
+    let client = client();
+
+    let outside = temp_path("outside.txt");
+
+    assert_eq!(Status::NotFound, status(&client, format!("/rooted/rooted-absolute/{}", segment(outside.to_str().unwrap()))));
+    assert_eq!(Status::NotFound, status(&client, format!("/rooted/rooted-absolute/{}", segment("/etc/passwd"))));
+}
+
+#[cfg(unix)]
+#[test]
+fn a_symlink_out_of_the_root_is_not_found() {
+    use std::os::unix::fs::symlink;
+
+    let root = root_dir("rooted-symlink");
+
+    let link = root.join("link.txt");
+
+    let _ = fs::remove_file(&link);
+
+    symlink(temp_path("outside.txt"), &link).unwrap();
+
+    let client = client();
+
+    assert_eq!(Status::NotFound, status(&client, "/rooted/rooted-symlink/link.txt".to_string()));
+}
+
+#[test]
+fn from_dir_serves_index_files_but_not_escapes() {
+    root_dir("dir");
+
+    let client = client();
+
+    let mut response = client.get("/dir/dir/sub").dispatch();
+
+    assert_eq!(Status::Ok, response.status());
+    assert_eq!(Some(b"<p>index</p>".to_vec()), response.body_bytes());
+
+    let outside = format!("../rocket-etagged-file-response-{}-outside.txt", process::id());
+
+    assert_eq!(Status::NotFound, status(&client, format!("/dir/dir/{}", segment(&outside))));
+    assert_eq!(Status::NotFound, status(&client, format!("/dir/dir/{}", segment("sub/../.."))));
+}
+
+#[cfg(unix)]
+#[test]
+fn from_dir_does_not_follow_an_index_file_out_of_the_root() {
+    use std::os::unix::fs::symlink;
+
+    let root = root_dir("dir-symlink");
+
+    let linked = root.join("linked");
+
+    fs::create_dir_all(&linked).unwrap();
+
+    let index = linked.join("index.html");
+
+    let _ = fs::remove_file(&index);
+
+    symlink(temp_path("outside.txt"), &index).unwrap();
+
+    let client = client();
+
+    assert_eq!(Status::NotFound, status(&client, "/dir/dir-symlink/linked".to_string()));
+}